@@ -1,21 +1,38 @@
-use bluetooth_hci::{
-    event::command::{CommandComplete, ReturnParameters},
-    host::uart::Packet,
-    Event,
-};
-use rf::hci::{
-    command::gatt::{
+use rf::{
+    ble::{Ble, BleError, BleTransportLayerError},
+    hci::command::gatt::{
         AddCharacteristicParameters, AddServiceParameters, CharacteristicEvent,
         CharacteristicHandle, CharacteristicPermission, CharacteristicProperty, EncryptionKeySize,
-        GattCommands, ServiceHandle, ServiceType, Uuid,
+        ServiceHandle, ServiceType, Uuid,
     },
-    event,
+    shci::gatt_db::{CharacteristicDesc, GattDb, ServiceDesc},
 };
 
-use crate::{helpers::receive_event_helper, Rc};
+use crate::DEVICE_NAME;
 
 type CP = CharacteristicProperty;
 
+const CHAR_VALUE_LEN: u16 = 16;
+
+const CHARACTERISTICS: &[CharacteristicDesc] = &[
+    CharacteristicDesc::new(Uuid::Uuid16(0x00FF), CHAR_VALUE_LEN, CP::READ.union(CP::WRITE)),
+    CharacteristicDesc::new(Uuid::Uuid16(0x00FE), CHAR_VALUE_LEN, CP::NOTIFY.union(CP::WRITE)),
+    CharacteristicDesc::new(Uuid::Uuid16(0x0605), CHAR_VALUE_LEN, CP::READ.union(CP::WRITE)),
+    CharacteristicDesc::new(Uuid::Uuid16(0x0505), CHAR_VALUE_LEN, CP::NOTIFY.union(CP::READ)),
+    CharacteristicDesc::new(Uuid::Uuid16(0x0405), CHAR_VALUE_LEN, CP::READ.union(CP::WRITE)),
+    CharacteristicDesc::new(Uuid::Uuid16(0x0305), CHAR_VALUE_LEN, CP::NOTIFY.union(CP::READ)),
+    CharacteristicDesc::new(Uuid::Uuid16(0x0205), CHAR_VALUE_LEN, CP::READ.union(CP::WRITE)),
+    CharacteristicDesc::new(Uuid::Uuid16(0x0105), CHAR_VALUE_LEN, CP::NOTIFY.union(CP::READ)),
+];
+
+const SERVICE: ServiceDesc = ServiceDesc::new(Uuid::Uuid16(0x5445), CHARACTERISTICS);
+
+/// evaluated at compile time, so that a service with too many attribute records fails the build
+const SERVICE_ATTRIBUTE_RECORDS: u8 = SERVICE.max_attribute_records();
+
+/// services registered by [`init_gatt_services`], sizes the GATT database of the BLE stack
+pub const GATT_DB: GattDb = GattDb::new(&[SERVICE]).device_name_len(DEVICE_NAME.len() as u8);
+
 pub struct BleContext {
     pub service_handle: ServiceHandle,
     // pub read_char_handle: CharacteristicHandle,
@@ -23,34 +40,25 @@ pub struct BleContext {
     // pub notify_char_handle: CharacteristicHandle,
 }
 
-pub async fn init_gatt_services(rc: &mut Rc) -> Result<BleContext, nb::Error<()>> {
+pub async fn init_gatt_services(
+    ble: &mut Ble,
+) -> Result<BleContext, BleError<BleTransportLayerError>> {
     defmt::info!("initializing services and characteristics");
 
-    let characteristics = [
-        (Uuid::Uuid16(0x00FF), CP::READ | CP::WRITE),
-        (Uuid::Uuid16(0x00FE), CP::NOTIFY | CP::WRITE),
-        (Uuid::Uuid16(0x0605), CP::READ | CP::WRITE),
-        (Uuid::Uuid16(0x0505), CP::NOTIFY | CP::READ),
-        (Uuid::Uuid16(0x0405), CP::READ | CP::WRITE),
-        (Uuid::Uuid16(0x0305), CP::NOTIFY | CP::READ),
-        (Uuid::Uuid16(0x0205), CP::READ | CP::WRITE),
-        (Uuid::Uuid16(0x0105), CP::NOTIFY | CP::READ),
-    ];
-
-    let service_handle = gatt_add_service(rc, Uuid::Uuid16(0x5445)).await?;
-
-    for char in characteristics {
-        gatt_add_char(rc, service_handle, char.0, char.1).await?;
+    let service_handle = gatt_add_service(ble, &SERVICE, SERVICE_ATTRIBUTE_RECORDS).await?;
+
+    for char in SERVICE.characteristics {
+        gatt_add_char(ble, service_handle, char).await?;
     }
 
     // let read_char_handle =
-    //     gatt_add_char(rc, service_handle, Uuid::Uuid16(0x501), CharacteristicProperty::READ)
+    //     gatt_add_char(ble, service_handle, Uuid::Uuid16(0x501), CharacteristicProperty::READ)
     //         .await?;
     // let write_char_handle =
-    //     gatt_add_char(rc, service_handle, Uuid::Uuid16(0x501), CharacteristicProperty::WRITE)
+    //     gatt_add_char(ble, service_handle, Uuid::Uuid16(0x501), CharacteristicProperty::WRITE)
     //         .await?;
     // let notify_char_handle = gatt_add_char(
-    //     rc,
+    //     ble,
     //     service_handle,
     //     Uuid::Uuid16(0x501),
     //     CharacteristicProperty::NOTIFY | CharacteristicProperty::READ,
@@ -65,60 +73,42 @@ pub async fn init_gatt_services(rc: &mut Rc) -> Result<BleContext, nb::Error<()>
     })
 }
 
-async fn gatt_add_service(rc: &mut Rc, uuid: Uuid) -> Result<ServiceHandle, nb::Error<()>> {
-    rc.add_service(&AddServiceParameters {
-        uuid,
-        service_type: ServiceType::Primary,
-        max_attribute_records: 32,
-    })?;
-    let result = receive_event_helper(rc, true).await?;
-
-    if let Packet::Event(Event::CommandComplete(CommandComplete {
-        return_params:
-            ReturnParameters::Vendor(event::command::ReturnParameters::GattAddService(
-                event::command::GattService { service_handle, .. },
-            )),
-        ..
-    })) = result
-    {
-        Ok(service_handle)
-    } else {
-        Err(nb::Error::Other(()))
-    }
+async fn gatt_add_service(
+    ble: &mut Ble,
+    service: &ServiceDesc<'_>,
+    max_attribute_records: u8,
+) -> Result<ServiceHandle, BleError<BleTransportLayerError>> {
+    let service = ble
+        .gatt()
+        .add_service(&AddServiceParameters {
+            uuid: service.uuid,
+            service_type: ServiceType::Primary,
+            max_attribute_records,
+        })
+        .await?;
+
+    Ok(service.service_handle)
 }
 
 async fn gatt_add_char(
-    rc: &mut Rc,
+    ble: &mut Ble,
     service_handle: ServiceHandle,
-    characteristic_uuid: Uuid,
-    characteristic_properties: CharacteristicProperty,
-) -> Result<CharacteristicHandle, nb::Error<()>> {
-    rc.add_characteristic(&AddCharacteristicParameters {
-        service_handle,
-        characteristic_uuid,
-        characteristic_properties,
-        characteristic_value_len: 16,
-        security_permissions: CharacteristicPermission::empty(),
-        gatt_event_mask: CharacteristicEvent::all(),
-        encryption_key_size: EncryptionKeySize::with_value(7).unwrap(),
-        is_variable: true,
-        fw_version_before_v72: false,
-    })?;
-    let result = receive_event_helper(rc, true).await?;
-
-    if let Packet::Event(Event::CommandComplete(CommandComplete {
-        return_params:
-            ReturnParameters::Vendor(event::command::ReturnParameters::GattAddCharacteristic(
-                event::command::GattCharacteristic {
-                    characteristic_handle,
-                    ..
-                },
-            )),
-        ..
-    })) = result
-    {
-        Ok(characteristic_handle)
-    } else {
-        Err(nb::Error::Other(()))
-    }
+    characteristic: &CharacteristicDesc<'_>,
+) -> Result<CharacteristicHandle, BleError<BleTransportLayerError>> {
+    let characteristic = ble
+        .gatt()
+        .add_characteristic(&AddCharacteristicParameters {
+            service_handle,
+            characteristic_uuid: characteristic.uuid,
+            characteristic_properties: characteristic.properties,
+            characteristic_value_len: characteristic.value_len as usize,
+            security_permissions: CharacteristicPermission::empty(),
+            gatt_event_mask: CharacteristicEvent::all(),
+            encryption_key_size: EncryptionKeySize::with_value(7).unwrap(),
+            is_variable: true,
+            fw_version_before_v72: false,
+        })
+        .await?;
+
+    Ok(characteristic.characteristic_handle)
 }
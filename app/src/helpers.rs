@@ -1,156 +1,93 @@
 use core::time::Duration;
 
 use bluetooth_hci::{
-    event::command::{CommandComplete, ReturnParameters},
-    host::{
-        uart::{Hci, Packet},
-        AdvertisingFilterPolicy, Hci as HostHci, OwnAddressType,
-    },
+    host::{uart::Packet, AdvertisingFilterPolicy, Hci as HostHci, OwnAddressType},
     types::AdvertisingType,
-    Event,
 };
-use rf::hci::{
-    command::{
-        gap::{DiscoverableParameters, GapCommands, IoCapability, LocalName, Role},
-        gatt::{GattCommands, UpdateCharacteristicValueParameters},
-        hal::{ConfigData, HalCommands},
+use rf::{
+    ble::{Ble, BleError, BleTransportLayerError},
+    hci::{
+        command::{
+            gap::{DiscoverableParameters, IoCapability, LocalName, Role},
+            gatt::UpdateCharacteristicValueParameters,
+            hal::ConfigData,
+        },
+        event::Stm32Wb5xEvent,
     },
-    event::{self, Stm32Wb5xError, Stm32Wb5xEvent},
-};
-
-use crate::{
-    utils::{get_bd_addr, get_erk, get_irk, get_random_addr},
-    Rc, STATE,
 };
 
-#[derive(defmt::Format)]
-struct OtherErrWrapper(#[defmt(Debug2Format)] bluetooth_hci::host::uart::Error<(), Stm32Wb5xError>);
-
-fn log_response_err(error: nb::Error<bluetooth_hci::host::uart::Error<(), Stm32Wb5xError>>) {
-    match error {
-        nb::Error::WouldBlock => {
-            // skip would block, as it will be resolved when the RX interruption is called
-        }
-        nb::Error::Other(err) => {
-            let wrapper = OtherErrWrapper(err);
-            defmt::warn!("Err {}", wrapper);
-        }
-    }
-}
-
-pub async fn receive_event_helper(
-    rc: &mut Rc,
-    need_cmd_rsp: bool,
-) -> Result<Packet<Stm32Wb5xEvent>, ()> {
-    loop {
-        let event = cortex_m::interrupt::free(|_| {
-            rc.process_events();
-            match rc.read() {
-                Ok(data) => Some(data),
-                Err(err) => {
-                    log_response_err(err);
-                    None
-                }
-            }
-        });
-
-        // If the receiver is only interested in command response events,
-        // it will be an error to return the first event from the event queue since
-        // it is not guaranteed that no events occurred in between of command execution and
-        // response.
-        // Thus we defer all of the non-command-response events into the temporary queue before
-        // we get a command response event that will be returned.
-
-        if need_cmd_rsp {
-            if let Some(event) = event {
-                if let Packet::Event(Event::CommandComplete(_)) = event {
-                    log_event(&event);
-
-                    return Ok(event);
-                } else {
-                    log_event(&event);
-                    // Defer the currently received event into temporary queue
-                    // for it to be processed later
-                }
-            }
-        } else if let Some(event) = event {
-            log_event(&event);
-            return Ok(event);
-        }
-
-        STATE.rx_int.wait().await;
-    }
-}
+use crate::utils::{get_bd_addr, get_erk, get_irk, get_random_addr};
 
 #[derive(defmt::Format)]
 struct EventWrapper(#[defmt(Debug2Format)] Packet<Stm32Wb5xEvent>);
 
-fn log_event(event: &Packet<Stm32Wb5xEvent>) {
+pub fn log_event(event: &Packet<Stm32Wb5xEvent>) {
     let wrapper = EventWrapper(event.clone());
     defmt::warn!("{}", wrapper)
 }
 
-pub async fn init_hal(rc: &mut Rc, device_name: &[u8]) -> Result<(), nb::Error<()>> {
+pub async fn init_hal(
+    ble: &mut Ble,
+    device_name: &[u8],
+) -> Result<(), BleError<BleTransportLayerError>> {
     defmt::info!("reset");
-    rc.reset()?;
-    let _ = receive_event_helper(rc, true).await?;
+    ble.perform_command(|rc| rc.reset()).await?;
 
     defmt::info!("public address");
-    rc.write_config_data(&ConfigData::public_address(get_bd_addr()).build())?;
-    let _ = receive_event_helper(rc, true).await?;
+    ble.hal()
+        .write_config_data(&ConfigData::public_address(get_bd_addr()).build())
+        .await?;
 
     defmt::info!("random address");
-    rc.write_config_data(&ConfigData::random_address(get_random_addr()).build())?;
-    let _ = receive_event_helper(rc, true).await?;
+    ble.hal()
+        .write_config_data(&ConfigData::random_address(get_random_addr()).build())
+        .await?;
 
     defmt::info!("identity root key");
-    rc.write_config_data(&ConfigData::identity_root(&get_irk()).build())?;
-    let _ = receive_event_helper(rc, true).await?;
+    ble.hal()
+        .write_config_data(&ConfigData::identity_root(&get_irk()).build())
+        .await?;
 
     defmt::info!("encryption root key");
-    rc.write_config_data(&ConfigData::encryption_root(&get_erk()).build())?;
-    let _ = receive_event_helper(rc, true).await?;
+    ble.hal()
+        .write_config_data(&ConfigData::encryption_root(&get_erk()).build())
+        .await?;
 
     defmt::info!("GATT init");
-    rc.init_gatt()?;
-    let _ = receive_event_helper(rc, true).await?;
+    ble.gatt().init().await?;
 
     defmt::info!("GAP init");
-    rc.init_gap(Role::PERIPHERAL, false, device_name.len() as u8)?;
-    let response = receive_event_helper(rc, true).await?;
-
-    let gap = if let Packet::Event(Event::CommandComplete(CommandComplete {
-        return_params: ReturnParameters::Vendor(event::command::ReturnParameters::GapInit(gap)),
-        ..
-    })) = response
-    {
-        gap
-    } else {
-        return Err(nb::Error::Other(()));
-    };
+    let gap = ble
+        .gap()
+        .init(Role::PERIPHERAL, false, device_name.len() as u8)
+        .await?;
 
     defmt::info!("update device name");
-    rc.update_characteristic_value(&UpdateCharacteristicValueParameters {
-        service_handle: gap.service_handle,
-        characteristic_handle: gap.dev_name_handle,
-        offset: 0,
-        value: device_name,
-    })
-    .map_err(|_| ())?;
-    let _ = receive_event_helper(rc, true).await?;
+    ble.gatt()
+        .update_characteristic_value(&UpdateCharacteristicValueParameters {
+            service_handle: gap.service_handle,
+            characteristic_handle: gap.dev_name_handle,
+            offset: 0,
+            value: device_name,
+        })
+        .await?;
 
     defmt::info!("set io capability");
-    rc.set_io_capability(IoCapability::DisplayConfirm)?;
-    let _ = receive_event_helper(rc, true).await?;
+    ble.gap()
+        .set_io_capability(IoCapability::DisplayConfirm)
+        .await?;
 
     defmt::info!("set scan response data");
-    rc.le_set_scan_response_data(&[]).map_err(|_| ())?;
-    let _ = receive_event_helper(rc, true).await?;
+    ble.perform_command(|rc| rc.le_set_scan_response_data(&[]).map_err(|e| e.map(|_| ())))
+        .await?;
 
     Ok(())
 }
 
-pub async fn set_discoverable(rc: &mut Rc, local_name: &[u8]) -> Result<(), nb::Error<()>> {
+pub async fn set_discoverable(
+    ble: &mut Ble,
+    local_name: &[u8],
+) -> Result<(), BleError<BleTransportLayerError>> {
     defmt::info!("set discoverable");
     let discovery_params = DiscoverableParameters {
         advertising_type: AdvertisingType::ConnectableUndirected,
@@ -162,8 +99,5 @@ pub async fn set_discoverable(rc: &mut Rc, local_name: &[u8]) -> Result<(), nb::
         conn_interval: (None, None),
     };
 
-    rc.set_discoverable(&discovery_params).map_err(|_| ())?;
-    let _ = receive_event_helper(rc, true).await?;
-
-    Ok(())
+    ble.gap().set_discoverable(&discovery_params).await
 }
@@ -2,26 +2,27 @@
 #![no_main]
 #![feature(type_alias_impl_trait)]
 
-use bbqueue::BBBuffer;
 use bluetooth_hci::{host::uart::Packet, Event};
 use embassy_executor::Spawner;
-use embassy_stm32::interrupt::{self, InterruptExt};
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_stm32::interrupt;
 use embedded_alloc::Heap;
 use rf::{
-    ble::RADIO_COPROCESSOR,
+    ble::Ble,
     hci::{
-        command::gatt::{GattCommands, WriteResponseParameters},
+        command::gatt::WriteResponseParameters,
         event::{FirmwareKind, Stm32Wb5xEvent},
-        RadioCoprocessor,
     },
     ipcc::Ipcc,
-    tl_mbox::{shci::ShciBleInitCmdParam, TlMbox},
+    shci::{
+        ble_init::{BleInitBuilder, BleStackVersion},
+        gatt_db::GattDbSize,
+    },
+    tl_mbox::TlMbox,
 };
 
 use crate::{
-    gatt::init_gatt_services,
-    helpers::{init_hal, receive_event_helper, set_discoverable},
+    gatt::{init_gatt_services, GATT_DB},
+    helpers::{init_hal, log_event, set_discoverable},
 };
 
 mod gatt;
@@ -33,20 +34,9 @@ use {defmt_rtt as _, panic_probe as _};
 #[global_allocator]
 static HEAP: Heap = Heap::empty();
 
-pub type Rc = RadioCoprocessor<'static, BUFFER_SIZE>;
-
-const BUFFER_SIZE: usize = 512;
-static BB: BBBuffer<BUFFER_SIZE> = BBBuffer::new();
-
-pub struct State {
-    tx_int: Signal<CriticalSectionRawMutex, ()>,
-    rx_int: Signal<CriticalSectionRawMutex, ()>,
-}
-
-pub static STATE: State = State {
-    tx_int: Signal::new(),
-    rx_int: Signal::new(),
-};
+const DEVICE_NAME: &[u8] = b"STM32WB55RGVx";
+const NUM_LINKS: u8 = 2;
+const GATT_DB_SIZE: GattDbSize = GATT_DB.size(NUM_LINKS);
 
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) -> ! {
@@ -67,109 +57,68 @@ async fn main(_spawner: Spawner) -> ! {
     let mut ipcc = Ipcc::new(p.IPCC, config);
     let mbox = TlMbox::init(&mut ipcc);
 
-    let config = ShciBleInitCmdParam {
-        p_ble_buffer_address: 0,
-        ble_buffer_size: 0,
-        num_attr_record: 68,
-        num_attr_serv: 8,
-        attr_value_arr_size: 1344,
-        num_of_links: 2,
-        extended_packet_length_enable: 1,
-        pr_write_list_size: 0x3A,
-        mb_lock_count: 0x79,
-        att_mtu: 156,
-        slave_sca: 500,
-        master_sca: 0,
-        ls_source: 1,
-        max_conn_event_length: 0xFFFFFFFF,
-        hs_startup_time: 0x148,
-        viterbi_enable: 1,
-        ll_only: 0,
-        hw_version: 0,
-    };
-
-    STATE.tx_int.reset();
-    STATE.rx_int.reset();
-
-    tx_irq.disable();
-    rx_irq.disable();
-
-    tx_irq.set_handler(|_| unsafe {
-        if let Some(rc) = RADIO_COPROCESSOR.as_mut() {
-            rc.handle_ipcc_tx();
-        }
-        STATE.tx_int.signal(());
-    });
-    rx_irq.set_handler(|_| unsafe {
-        if let Some(rc) = RADIO_COPROCESSOR.as_mut() {
-            rc.handle_ipcc_rx();
-        }
-        STATE.rx_int.signal(());
-    });
+    let config = BleInitBuilder::new(BleStackVersion::V1_11)
+        .num_links(NUM_LINKS)
+        .gatt_db_size(GATT_DB_SIZE)
+        .build()
+        .unwrap();
 
-    let (producer, consumer) = BB.try_split().unwrap();
-    let mut rc = Rc::new(producer, consumer, mbox, ipcc, config);
-    unsafe {
-        RADIO_COPROCESSOR = &mut rc;
+    let mut ble = Ble::new(rx_irq, tx_irq, mbox, ipcc).await.unwrap();
+    match ble.firmware() {
+        FirmwareKind::Wireless => defmt::info!("starting BLE"),
+        firmware => defmt::panic!("CPU2 does not run the wireless firmware: {}", firmware),
     }
 
-    tx_irq.enable();
-    rx_irq.enable();
-
-    let res = receive_event_helper(&mut rc, false).await;
-    if let Ok(Packet::Event(Event::Vendor(Stm32Wb5xEvent::CoprocessorReady(
-        FirmwareKind::Wireless,
-    )))) = res
-    {
-        defmt::info!("starting BLE");
-    }
+    ble.start_ble(config, None).await.unwrap();
 
-    init_hal(&mut rc, b"STM32WB55RGVx").await.unwrap();
-    let _ble_context = init_gatt_services(&mut rc).await.unwrap();
-    set_discoverable(&mut rc, b"STM32WB55RGVx").await.unwrap();
+    init_hal(&mut ble, DEVICE_NAME).await.unwrap();
+    let _ble_context = init_gatt_services(&mut ble).await.unwrap();
+    set_discoverable(&mut ble, DEVICE_NAME).await.unwrap();
 
     defmt::info!("done");
 
     loop {
-        let return_params = receive_event_helper(&mut rc, false).await;
+        let return_params = ble.receive_event().await;
+        if let Ok(event) = &return_params {
+            log_event(event);
+        }
 
         if let Ok(Packet::Event(event)) = return_params {
             match event {
                 Event::Vendor(vendor_event) => match vendor_event {
                     Stm32Wb5xEvent::AttReadPermitRequest(read_req) => {
                         defmt::info!("allowing read");
-                        rc.allow_read(read_req.conn_handle).unwrap();
-                        let _ = receive_event_helper(&mut rc, true).await;
+                        let _ = ble.gatt().allow_read(read_req.conn_handle).await;
                     }
                     Stm32Wb5xEvent::AttWritePermitRequest(write_req) => {
                         defmt::info!("allowing write");
-                        rc.write_response(&WriteResponseParameters {
-                            attribute_handle: write_req.attribute_handle,
-                            conn_handle: write_req.conn_handle,
-                            status: Ok(()),
-                            value: write_req.value(),
-                        })
-                        .unwrap();
-                        let _ = receive_event_helper(&mut rc, false).await;
+                        let _ = ble
+                            .gatt()
+                            .write_response(&WriteResponseParameters {
+                                attribute_handle: write_req.attribute_handle,
+                                conn_handle: write_req.conn_handle,
+                                status: Ok(()),
+                                value: write_req.value(),
+                            })
+                            .await;
                     }
                     // Stm32Wb5xEvent::GattAttributeModified(_attribute) => {
                     //     Timer::after(embassy_time::Duration::from_millis(2000)).await;
 
                     //     defmt::info!("sending a notification");
-                    //     rc.update_characteristic_value(&UpdateCharacteristicValueParameters {
+                    //     let params = UpdateCharacteristicValueParameters {
                     //         characteristic_handle: ble_context.notify_char_handle,
                     //         service_handle: ble_context.service_handle,
                     //         offset: 0,
                     //         value: b"hello world",
-                    //     })
-                    //     .unwrap();
-                    //     let _ = receive_event_helper(&mut rc, false).await;
+                    //     };
+                    //     let _ = ble.gatt().update_characteristic_value(&params).await;
                     // }
                     _ => {}
                 },
                 Event::DisconnectionComplete(_) => {
                     defmt::info!("disconnected, readvertising");
-                    set_discoverable(&mut rc, b"STM32WB55RGVx").await.unwrap();
+                    set_discoverable(&mut ble, DEVICE_NAME).await.unwrap();
                 }
                 _ => {}
             }
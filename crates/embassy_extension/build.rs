@@ -0,0 +1,94 @@
+//! This build script generates the `tl_mbox.x` linker fragment that places the mailbox
+//! shared memory sections (`TL_REF_TABLE`, `MB_MEM1` and `MB_MEM2`) where CPU2 expects them.
+//!
+//! The application's `memory.x` only has to `INCLUDE tl_mbox.x`; the directory holding the
+//! fragment is added to the linker search path of every crate depending on this one.
+//!
+//! The fragment also contains linker assertions, so a layout that CPU2 could not read fails the
+//! link instead of failing silently at runtime. The same SRAM2 map is written to `sram2.rs` for
+//! the runtime checks of `tl_mbox::layout`.
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// SRAM2 map of the STM32WB55, as documented in RM0434.
+struct Sram2 {
+    sram2a_origin: u32,
+    sram2a_length: u32,
+    sram2b_origin: u32,
+    sram2b_length: u32,
+    /// part of SRAM2a that is reserved for the mailbox (`RAM_SHARED` in ST's linker scripts)
+    shared_length: u32,
+}
+
+const SRAM2: Sram2 = Sram2 {
+    sram2a_origin: 0x2003_0000,
+    sram2a_length: 32 * 1024,
+    sram2b_origin: 0x2003_8000,
+    sram2b_length: 32 * 1024,
+    shared_length: 10 * 1024,
+};
+
+fn main() {
+    let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    File::create(out.join("tl_mbox.x"))
+        .unwrap()
+        .write_all(fragment(&SRAM2).as_bytes())
+        .unwrap();
+    File::create(out.join("sram2.rs"))
+        .unwrap()
+        .write_all(constants(&SRAM2).as_bytes())
+        .unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+/// returns the SRAM2 map as the constants of `tl_mbox::layout`
+fn constants(m: &Sram2) -> String {
+    format!(
+        "/// Start of SRAM2a. CPU2 expects the reference table at this address.
+pub const SRAM2A_BASE: usize = {sram2a_origin:#010x};
+/// Size of SRAM2a, in bytes.
+pub const SRAM2A_SIZE: usize = {sram2a_length};
+/// Start of SRAM2b.
+pub const SRAM2B_BASE: usize = {sram2b_origin:#010x};
+/// Size of SRAM2b, in bytes.
+pub const SRAM2B_SIZE: usize = {sram2b_length};
+",
+        sram2a_origin = m.sram2a_origin,
+        sram2a_length = m.sram2a_length,
+        sram2b_origin = m.sram2b_origin,
+        sram2b_length = m.sram2b_length,
+    )
+}
+
+fn fragment(m: &Sram2) -> String {
+    format!(
+        "/* Mailbox shared memory layout for STM32WB55, generated by the `rf` crate. */
+
+MEMORY
+{{
+    RAM_SHARED (xrw)           : ORIGIN = {sram2a_origin:#010x}, LENGTH = {shared_length}
+}}
+
+SECTIONS {{
+    TL_REF_TABLE                     (NOLOAD) : {{ *(TL_REF_TABLE) }} >RAM_SHARED
+
+    MB_MEM1 (NOLOAD)                          : {{ *(MB_MEM1) }} >RAM_SHARED
+    MB_MEM2 (NOLOAD)                          : {{ _sMB_MEM2 = . ; *(MB_MEM2) ; _eMB_MEM2 = . ; }} >RAM_SHARED
+}}
+
+/* CPU2 reads the address of the reference table from the first word of SRAM2a */
+ASSERT(ADDR(TL_REF_TABLE) == {sram2a_origin:#010x}, \"TL_REF_TABLE must be placed at the start of SRAM2a\");
+
+ASSERT(ADDR(MB_MEM1) >= {sram2a_origin:#010x} && ADDR(MB_MEM1) + SIZEOF(MB_MEM1) <= {sram2b_end:#010x}, \"MB_MEM1 must be placed in SRAM2a/SRAM2b\");
+ASSERT(ADDR(MB_MEM2) >= {sram2a_origin:#010x} && ADDR(MB_MEM2) + SIZEOF(MB_MEM2) <= {sram2b_end:#010x}, \"MB_MEM2 must be placed in SRAM2a/SRAM2b\");
+",
+        sram2a_origin = m.sram2a_origin,
+        shared_length = m.shared_length.min(m.sram2a_length),
+        sram2b_end = m.sram2b_origin + m.sram2b_length,
+    )
+}
@@ -1,27 +1,63 @@
+use core::cell::RefCell;
+
 use crate::{
+    concurrent::Concurrent,
+    flash::FlashGuard,
+    fus::Fus,
+    gap::Gap,
+    gatt::Gatt,
+    hal::Hal,
     hci::{
-        event::{FirmwareKind, Stm32Wb5xError, Stm32Wb5xEvent},
+        acl::AclData,
+        event::{
+            command::ReturnParameters as VendorReturnParameters, FirmwareKind, Status,
+            Stm32Wb5xError, Stm32Wb5xEvent,
+        },
+        flow::CommandDiagnostic,
         RadioCoprocessor,
     },
+    hsem::Hsem,
     ipcc::Ipcc,
-    tl_mbox::{shci::ShciBleInitCmdParam, TlMbox},
+    shci::{ble_init::BleInitConfig, event::SysEvent, Shci},
+    tl_mbox::{
+        shci::{ShciConfigParam, ShciResponse, ShciStatus},
+        snapshot::MboxSnapshot,
+        stats::{Counter, MboxStats},
+        TlMbox,
+    },
 };
 use bbqueue::BBBuffer;
 use bluetooth_hci::{
     event::command::{CommandComplete, ReturnParameters},
     host::uart::{Error, Hci, Packet},
-    Event,
+    ConnectionHandle, Event, Opcode,
 };
 use embassy_stm32::interrupt::{self, InterruptExt};
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    signal::Signal,
+};
+use embassy_time::{with_timeout, Duration, Instant};
 
 type HeaplessEvtQueue = heapless::spsc::Queue<Packet<Stm32Wb5xEvent>, 32>;
 pub type Rc = RadioCoprocessor<'static, BUFFER_SIZE>;
 
 const BUFFER_SIZE: usize = 512;
 
+/// Time a command waits for its response before [`BleError::Timeout`], the HCI timeout of ST's
+/// transport layer.
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
 static BB: BBBuffer<BUFFER_SIZE> = BBBuffer::new();
-pub static mut RADIO_COPROCESSOR: *mut Rc = core::ptr::null_mut();
+
+/// The radio coprocessor, moved in by [`Ble::new`] and shared with the IPCC interrupt handlers.
+static RADIO_COPROCESSOR: Mutex<CriticalSectionRawMutex, RefCell<Option<Rc>>> =
+    Mutex::new(RefCell::new(None));
+
+/// runs `f` on the radio coprocessor in a critical section, returns `None` without a [`Ble`]
+pub(crate) fn with_rc<R>(f: impl FnOnce(&mut Rc) -> R) -> Option<R> {
+    RADIO_COPROCESSOR.lock(|rc| rc.borrow_mut().as_mut().map(f))
+}
 
 /// Type alias for the BLE stack's transport layer errors.
 pub type BleTransportLayerError = bluetooth_hci::host::uart::Error<(), Stm32Wb5xError>;
@@ -33,6 +69,29 @@ pub enum BleError<E: core::fmt::Debug> {
     EmptyError,
     UnexpectedEvent,
     NotInitialized,
+    /// CPU2 rejected a system command
+    ShciError(ShciStatus),
+    /// a command payload does not fit in the command buffer, includes its length
+    PayloadTooLong(usize),
+    /// the arguments of the call cannot be sent in a command
+    InvalidArgument,
+    /// CPU2 does not run the firmware the operation needs, includes the firmware it runs
+    WrongFirmware(FirmwareKind),
+    /// the controller rejected a command, with a command status event or in the return
+    /// parameters of a typed command
+    CommandFailed(bluetooth_hci::Status<Status>),
+    /// the controller did not answer a command, or end a procedure, in time
+    Timeout(CommandTimeout),
+}
+
+/// A command the controller did not answer in time, see [`Ble::set_timeout_hook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandTimeout {
+    /// opcode of the command CPU2 did not answer, `None` if the command was still held until
+    /// a credit is granted or if the end of a procedure was awaited
+    pub opcode: Option<u16>,
+    /// time the command waited for its response
+    pub timeout: Duration,
 }
 
 impl<E: core::fmt::Debug> From<nb::Error<()>> for BleError<E> {
@@ -47,6 +106,90 @@ impl From<nb::Error<BleTransportLayerError>> for BleError<BleTransportLayerError
     }
 }
 
+/// Response of the controller to a command, see [`Ble::perform_command`].
+#[derive(Debug)]
+pub enum CommandResponse {
+    /// the command completed, with its return parameters
+    Complete(ReturnParameters<Stm32Wb5xEvent>),
+    /// the controller accepted the command, the procedure it started reports its result with a
+    /// later event, see [`Completion`]
+    Pending(Opcode),
+}
+
+impl CommandResponse {
+    /// returns the return parameters of a completed command
+    pub fn into_complete(self) -> Option<ReturnParameters<Stm32Wb5xEvent>> {
+        match self {
+            CommandResponse::Complete(return_params) => Some(return_params),
+            CommandResponse::Pending(_) => None,
+        }
+    }
+}
+
+/// Event that ends a procedure started by a command answered with a command status, see
+/// [`Ble::perform_procedure`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Completion {
+    /// GAP procedure complete, ends the discovery, connection establishment and name discovery
+    /// procedures
+    GapProcedure,
+    /// GATT procedure complete on the connection, ends the GATT client procedures
+    GattProcedure(ConnectionHandle),
+    /// disconnection complete on the connection, ends a terminate command
+    Disconnection(ConnectionHandle),
+    /// LE connection complete, ends a create connection command
+    LeConnection,
+}
+
+impl Completion {
+    /// returns `true` if `event` ends the procedure
+    pub fn matches(&self, event: &Packet<Stm32Wb5xEvent>) -> bool {
+        let event = match event {
+            Packet::Event(event) => event,
+            _ => return false,
+        };
+
+        match (self, event) {
+            (Completion::GapProcedure, Event::Vendor(Stm32Wb5xEvent::GapProcedureComplete(_))) => {
+                true
+            }
+            (
+                Completion::GattProcedure(handle),
+                Event::Vendor(Stm32Wb5xEvent::GattProcedureComplete(complete)),
+            ) => complete.conn_handle == *handle,
+            (Completion::Disconnection(handle), Event::DisconnectionComplete(complete)) => {
+                complete.conn_handle == *handle
+            }
+            (Completion::LeConnection, Event::LeConnectionComplete(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Events [`Ble::receive_event_helper`] waits for, the other ones are deferred.
+enum Awaited {
+    Any,
+    CommandResponse,
+    Completion(Completion),
+}
+
+impl Awaited {
+    fn matches(&self, event: &Packet<Stm32Wb5xEvent>) -> bool {
+        match self {
+            Awaited::Any => true,
+            // the events of opcode 0 only grant credits, they do not answer the command
+            Awaited::CommandResponse => match event {
+                Packet::Event(Event::CommandComplete(complete)) => {
+                    !matches!(complete.return_params, ReturnParameters::Spontaneous)
+                }
+                Packet::Event(Event::CommandStatus(status)) => status.opcode.0 != 0,
+                _ => false,
+            },
+            Awaited::Completion(completion) => completion.matches(event),
+        }
+    }
+}
+
 struct State {
     tx_int: Signal<CriticalSectionRawMutex, ()>,
     rx_int: Signal<CriticalSectionRawMutex, ()>,
@@ -57,18 +200,105 @@ static STATE: State = State {
     rx_int: Signal::new(),
 };
 
+struct Counters {
+    deferred_events_high_water: Counter,
+    deferred_events_overflows: Counter,
+    commands_completed: Counter,
+    last_command_latency_us: Counter,
+    max_command_latency_us: Counter,
+    total_command_latency_us: Counter,
+}
+
+static COUNTERS: Counters = Counters {
+    deferred_events_high_water: Counter::new(),
+    deferred_events_overflows: Counter::new(),
+    commands_completed: Counter::new(),
+    last_command_latency_us: Counter::new(),
+    max_command_latency_us: Counter::new(),
+    total_command_latency_us: Counter::new(),
+};
+
+/// Snapshot of the BLE stack health counters, see [`Ble::stats`].
+#[derive(Debug, Clone, Copy, Default, defmt::Format)]
+pub struct BleStats {
+    /// mailbox counters
+    pub mbox: MboxStats,
+    /// largest number of bytes that were waiting in the HCI buffer
+    pub hci_buffer_high_water: u32,
+    /// largest number of events deferred while waiting for a command response
+    pub deferred_events_high_water: u32,
+    /// events dropped because the deferred event queue was full
+    pub deferred_events_overflows: u32,
+    /// commands that received their response
+    pub commands_completed: u32,
+    /// time between sending the last command and receiving its response, in microseconds
+    pub last_command_latency_us: u32,
+    /// longest time a command waited for its response, in microseconds
+    pub max_command_latency_us: u32,
+    /// sum of all command latencies, in microseconds
+    pub total_command_latency_us: u32,
+}
+
+impl BleStats {
+    /// average time a command waited for its response, in microseconds
+    pub fn average_command_latency_us(&self) -> u32 {
+        self.total_command_latency_us
+            .checked_div(self.commands_completed)
+            .unwrap_or(0)
+    }
+}
+
 pub struct Ble {
     rx_int: interrupt::IPCC_C1_RX,
     tx_int: interrupt::IPCC_C1_TX,
     deferred_events: HeaplessEvtQueue,
+    firmware: FirmwareKind,
+    ble_ready: bool,
+    command_timeout: Duration,
+    timeout_hook: Option<fn(&CommandTimeout)>,
 }
 
 impl Ble {
-    /// initializes the BLE stack and returns a status response from the BLE stack.
+    /// waits for CPU2 to be ready and initializes the BLE stack.
+    ///
+    /// If CPU2 starts the Firmware Upgrade Service instead of the wireless firmware, the BLE stack
+    /// is not initialized, see [`Ble::firmware`].
     pub async fn init(
         rx_int: interrupt::IPCC_C1_RX,
         tx_int: interrupt::IPCC_C1_TX,
-        ble_config: ShciBleInitCmdParam,
+        ble_config: impl Into<BleInitConfig>,
+        mbox: TlMbox,
+        ipcc: Ipcc<'static>,
+    ) -> Result<Self, BleError<Error<(), Stm32Wb5xError>>> {
+        Self::init_with_c2_config(rx_int, tx_int, ble_config, None, mbox, ipcc).await
+    }
+
+    /// same as [`Ble::init`], but also configures CPU2 with `c2_config` before the BLE stack is
+    /// initialized, e.g. to keep the BLE NVM data in SRAM, see [`crate::nvm`].
+    pub async fn init_with_c2_config(
+        rx_int: interrupt::IPCC_C1_RX,
+        tx_int: interrupt::IPCC_C1_TX,
+        ble_config: impl Into<BleInitConfig>,
+        c2_config: Option<ShciConfigParam>,
+        mbox: TlMbox,
+        ipcc: Ipcc<'static>,
+    ) -> Result<Self, BleError<Error<(), Stm32Wb5xError>>> {
+        let mut ble = Self::new(rx_int, tx_int, mbox, ipcc).await?;
+        if ble.firmware == FirmwareKind::Wireless {
+            ble.start_ble(ble_config, c2_config).await?;
+        }
+
+        Ok(ble)
+    }
+
+    /// waits for CPU2 to be ready without starting a wireless stack.
+    ///
+    /// Depending on [`Ble::firmware`], the application then starts the BLE stack with
+    /// [`Ble::start_ble`], another stack, e.g. with [`Concurrent::thread_init`], or uses the
+    /// Firmware Upgrade Service with [`Ble::fus`].
+    pub async fn new(
+        rx_int: interrupt::IPCC_C1_RX,
+        tx_int: interrupt::IPCC_C1_TX,
         mbox: TlMbox,
         ipcc: Ipcc<'static>,
     ) -> Result<Self, BleError<Error<(), Stm32Wb5xError>>> {
@@ -85,99 +315,502 @@ impl Ble {
         rx_int.set_handler_context(core::ptr::null_mut());
 
         let (producer, consumer) = BB.try_split().unwrap();
-        let mut rc = Rc::new(producer, consumer, mbox, ipcc, ble_config);
-        unsafe {
-            RADIO_COPROCESSOR = &mut rc;
-        }
+        let rc = Rc::new(producer, consumer, mbox, ipcc);
+        RADIO_COPROCESSOR.lock(|cell| *cell.borrow_mut() = Some(rc));
 
         tx_int.enable();
         rx_int.enable();
 
-        let mut evt_queue = heapless::spsc::Queue::new();
-        match Self::receive_event_helper(&mut evt_queue, &mut rc, false).await {
-            Ok(Packet::Event(Event::Vendor(Stm32Wb5xEvent::CoprocessorReady(
-                FirmwareKind::Wireless,
-            )))) => Ok(Self {
-                rx_int,
-                tx_int,
-                deferred_events: evt_queue,
-            }),
-            Err(e) => Err(BleError::NbError(e)),
-            _ => Err(BleError::UnexpectedEvent),
+        let firmware = Self::receive_c2_ready().await;
+
+        Ok(Self {
+            rx_int,
+            tx_int,
+            deferred_events: heapless::spsc::Queue::new(),
+            firmware,
+            ble_ready: false,
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+            timeout_hook: None,
+        })
+    }
+
+    /// starts the BLE stack, after configuring CPU2 with `c2_config` if it is given.
+    ///
+    /// Fails if CPU2 does not run the wireless firmware or rejects one of the commands.
+    pub async fn start_ble(
+        &mut self,
+        ble_config: impl Into<BleInitConfig>,
+        c2_config: Option<ShciConfigParam>,
+    ) -> Result<(), BleError<Error<(), Stm32Wb5xError>>> {
+        if self.firmware != FirmwareKind::Wireless {
+            return Err(BleError::WrongFirmware(self.firmware));
+        }
+
+        if let Some(c2_config) = c2_config {
+            self.shci().c2_config(c2_config).await?;
         }
+        self.shci().c2_ble_init(ble_config).await?;
+        self.ble_ready = true;
+
+        Ok(())
+    }
+
+    /// returns `true` once the BLE stack is started and accepts HCI commands
+    pub fn is_ble_ready(&self) -> bool {
+        self.ble_ready
+    }
+
+    /// returns the firmware CPU2 reported when it last started.
+    ///
+    /// When it is [`FirmwareKind::Fus`], the BLE stack is not running and only the commands of
+    /// [`Ble::fus`] are accepted.
+    pub fn firmware(&self) -> FirmwareKind {
+        self.firmware
+    }
+
+    /// returns a handle to send Firmware Upgrade Service commands to CPU2
+    pub fn fus(&mut self) -> Fus<'_> {
+        Fus::new(self)
+    }
+
+    /// waits until CPU2 restarts, e.g. after a FUS command.
+    ///
+    /// No wireless stack runs after a restart, the BLE stack is started again with
+    /// [`Ble::start_ble`].
+    pub async fn wait_coprocessor_ready(
+        &mut self,
+    ) -> Result<FirmwareKind, BleError<Error<(), Stm32Wb5xError>>> {
+        self.firmware = Self::receive_c2_ready().await;
+        self.ble_ready = false;
+
+        Ok(self.firmware)
+    }
+
+    /// returns a handle to run the Thread stack next to the BLE stack, on the concurrent
+    /// wireless firmware
+    pub fn concurrent(&mut self) -> Concurrent<'_> {
+        Concurrent::new(self)
+    }
+
+    /// returns a handle to erase and program the flash without disturbing CPU2, `hsem` waits
+    /// for the semaphores CPU2 holds
+    pub async fn flash_guard<'a>(
+        &'a mut self,
+        hsem: &'a Hsem,
+    ) -> Result<FlashGuard<'a>, BleError<BleTransportLayerError>> {
+        FlashGuard::new(self, hsem).await
+    }
+
+    /// returns a handle to send system commands to CPU2
+    pub fn shci(&mut self) -> Shci<'_> {
+        Shci::new(self)
+    }
+
+    /// returns a handle to send HAL commands to the BLE stack
+    pub fn hal(&mut self) -> Hal<'_> {
+        Hal::new(self)
+    }
+
+    /// returns a handle to send GAP commands to the BLE stack
+    pub fn gap(&mut self) -> Gap<'_> {
+        Gap::new(self)
+    }
+
+    /// returns a handle to send GATT commands to the BLE stack
+    pub fn gatt(&mut self) -> Gatt<'_> {
+        Gatt::new(self)
+    }
+
+    /// Sends an SHCI command and awaits for its response from CPU2.
+    ///
+    /// The status of the response is not checked, see [`Shci`] for typed commands.
+    pub async fn perform_shci_command(
+        &mut self,
+        opcode: u16,
+        payload: &[u8],
+    ) -> Result<ShciResponse, BleError<Error<(), Stm32Wb5xError>>> {
+        self.with_coprocessor(|rc| {
+            // discard a response nobody waited for
+            rc.take_shci_response();
+            rc.write_shci_command(opcode, payload)
+        })?
+        .map_err(|_| BleError::PayloadTooLong(payload.len()))?;
+
+        Ok(Self::receive_shci_response(opcode).await)
     }
 
     /// Sends an HCI BLE command and awaits for a response from the BLE stack.
+    ///
+    /// Commands that start a procedure are answered with a command status, which gives
+    /// [`CommandResponse::Pending`] or [`BleError::CommandFailed`]. The end of the procedure is
+    /// awaited with [`Ble::wait_completion`], or the whole exchange is performed with
+    /// [`Ble::perform_procedure`].
+    ///
+    /// The status of a command complete is not checked, see [`Ble::hal`], [`Ble::gap`] and
+    /// [`Ble::gatt`] for typed commands.
+    ///
+    /// Fails with [`BleError::NotInitialized`] until the BLE stack is started.
     pub async fn perform_command(
         &mut self,
         command: impl Fn(&mut Rc) -> nb::Result<(), ()>,
-    ) -> Result<ReturnParameters<Stm32Wb5xEvent>, BleError<Error<(), Stm32Wb5xError>>> {
-        let rc = unsafe { RADIO_COPROCESSOR.as_mut() };
-        if let Some(rc) = rc {
-            cortex_m::interrupt::free(|_| command(rc))?;
-            let response = Self::receive_event_helper(&mut self.deferred_events, rc, true).await?;
-            if let Packet::Event(Event::CommandComplete(CommandComplete {
-                return_params, ..
-            })) = response
-            {
-                Ok(return_params)
-            } else {
-                Err(BleError::UnexpectedEvent)
+    ) -> Result<CommandResponse, BleError<Error<(), Stm32Wb5xError>>> {
+        self.perform_command_with_timeout(command, self.command_timeout)
+            .await
+    }
+
+    /// same as [`Ble::perform_command`], but waits at most `timeout` for the response instead
+    /// of [`Ble::command_timeout`].
+    ///
+    /// Once the timeout expired, the command is abandoned, its late response is reported by
+    /// [`Ble::read_diagnostic`], and the hook set with [`Ble::set_timeout_hook`] is called. The
+    /// next command is only sent once the late response arrived, since CPU2 may still read the
+    /// command buffer until then, or once the stack was restarted.
+    pub async fn perform_command_with_timeout(
+        &mut self,
+        command: impl Fn(&mut Rc) -> nb::Result<(), ()>,
+        timeout: Duration,
+    ) -> Result<CommandResponse, BleError<Error<(), Stm32Wb5xError>>> {
+        if !self.ble_ready {
+            return Err(BleError::NotInitialized);
+        }
+
+        let response =
+            with_timeout(timeout, Self::command_helper(&mut self.deferred_events, command)).await;
+
+        let response = match response {
+            Ok(response) => response?,
+            Err(_) => {
+                let timeout = self.with_coprocessor(|rc| {
+                    let opcode = rc.command_in_flight();
+                    rc.abandon_command();
+                    rc.clear_queued_commands();
+
+                    CommandTimeout { opcode, timeout }
+                })?;
+
+                defmt::warn!("command {} timed out", timeout.opcode);
+                if let Some(hook) = self.timeout_hook {
+                    hook(&timeout);
+                }
+
+                return Err(BleError::Timeout(timeout));
             }
-        } else {
-            Err(BleError::NotInitialized)
+        };
+
+        match response {
+            Packet::Event(Event::CommandComplete(CommandComplete { return_params, .. })) => {
+                Ok(CommandResponse::Complete(return_params))
+            }
+            Packet::Event(Event::CommandStatus(status)) => match status.status {
+                bluetooth_hci::Status::Success => Ok(CommandResponse::Pending(status.opcode)),
+                error => Err(BleError::CommandFailed(error)),
+            },
+            _ => Err(BleError::UnexpectedEvent),
+        }
+    }
+
+    /// returns the time [`Ble::perform_command`] waits for a response
+    pub fn command_timeout(&self) -> Duration {
+        self.command_timeout
+    }
+
+    /// sets the time [`Ble::perform_command`] waits for a response, [`DEFAULT_COMMAND_TIMEOUT`]
+    /// by default
+    pub fn set_command_timeout(&mut self, timeout: Duration) {
+        self.command_timeout = timeout;
+    }
+
+    /// sets the function called when the controller did not answer a command in time.
+    ///
+    /// The controller rarely recovers by itself, the hook lets the application log the state
+    /// of the mailbox and reset the stack or the whole chip, e.g. with
+    /// `cortex_m::peripheral::SCB::sys_reset`.
+    pub fn set_timeout_hook(&mut self, hook: fn(&CommandTimeout)) {
+        self.timeout_hook = Some(hook);
+    }
+
+    /// Sends an HCI BLE command that starts a procedure and awaits the event that ends it.
+    ///
+    /// Events received in the meantime are kept for [`Ble::receive_event`].
+    pub async fn perform_procedure(
+        &mut self,
+        command: impl Fn(&mut Rc) -> nb::Result<(), ()>,
+        completion: Completion,
+    ) -> Result<Packet<Stm32Wb5xEvent>, BleError<Error<(), Stm32Wb5xError>>> {
+        match self.perform_command(command).await? {
+            CommandResponse::Pending(_) => self.wait_completion(completion).await,
+            CommandResponse::Complete(_) => Err(BleError::UnexpectedEvent),
+        }
+    }
+
+    /// Sends a vendor command with [`Ble::perform_command`] and decodes the return parameters of
+    /// its command complete event, for the typed commands of [`Ble::hal`], [`Ble::gap`] and
+    /// [`Ble::gatt`].
+    ///
+    /// `decode` picks the return parameters of the command and splits them into their status and
+    /// the returned value. Other return parameters give [`BleError::UnexpectedEvent`], a status
+    /// other than success gives [`BleError::CommandFailed`].
+    pub(crate) async fn perform_vendor_command<T>(
+        &mut self,
+        command: impl Fn(&mut Rc) -> nb::Result<(), ()>,
+        decode: impl FnOnce(VendorReturnParameters) -> Option<(bluetooth_hci::Status<Status>, T)>,
+    ) -> Result<T, BleError<Error<(), Stm32Wb5xError>>> {
+        let return_params = match self.perform_command(command).await?.into_complete() {
+            Some(ReturnParameters::Vendor(return_params)) => return_params,
+            _ => return Err(BleError::UnexpectedEvent),
+        };
+
+        match decode(return_params) {
+            Some((bluetooth_hci::Status::Success, value)) => Ok(value),
+            Some((status, _)) => Err(BleError::CommandFailed(status)),
+            None => Err(BleError::UnexpectedEvent),
+        }
+    }
+
+    /// awaits the event that ends a procedure, events received in the meantime are kept for
+    /// [`Ble::receive_event`].
+    ///
+    /// Waits at most [`Ble::command_timeout`], see [`Ble::wait_completion_with_timeout`].
+    pub async fn wait_completion(
+        &mut self,
+        completion: Completion,
+    ) -> Result<Packet<Stm32Wb5xEvent>, BleError<Error<(), Stm32Wb5xError>>> {
+        self.wait_completion_with_timeout(completion, self.command_timeout)
+            .await
+    }
+
+    /// same as [`Ble::wait_completion`], but waits at most `timeout` for the end of the
+    /// procedure instead of [`Ble::command_timeout`].
+    ///
+    /// Fails with [`BleError::Timeout`] once the timeout expired, the hook set with
+    /// [`Ble::set_timeout_hook`] is not called since the controller answered the command.
+    pub async fn wait_completion_with_timeout(
+        &mut self,
+        completion: Completion,
+        timeout: Duration,
+    ) -> Result<Packet<Stm32Wb5xEvent>, BleError<Error<(), Stm32Wb5xError>>> {
+        let event = with_timeout(
+            timeout,
+            Self::receive_event_helper(&mut self.deferred_events, Awaited::Completion(completion)),
+        )
+        .await;
+
+        match event {
+            Ok(event) => Ok(event?),
+            Err(_) => Err(BleError::Timeout(CommandTimeout {
+                opcode: None,
+                timeout,
+            })),
         }
     }
 
     pub async fn receive_event(
         &mut self,
     ) -> Result<Packet<Stm32Wb5xEvent>, BleError<Error<(), Stm32Wb5xError>>> {
-        let rc = unsafe { RADIO_COPROCESSOR.as_mut() };
-        if let Some(rc) = rc {
-            let event = Self::receive_event_helper(&mut self.deferred_events, rc, false).await;
-            match event {
-                Ok(event) => Ok(event),
-                Err(_) => Err(BleError::UnexpectedEvent),
+        let event = Self::receive_event_helper(&mut self.deferred_events, Awaited::Any).await;
+        match event {
+            Ok(event) => Ok(event),
+            Err(_) => Err(BleError::UnexpectedEvent),
+        }
+    }
+
+    /// sends an ACL data packet, waiting until CPU2 released the buffer of the previous one
+    pub async fn send_acl_data(
+        &mut self,
+        packet: &AclData,
+    ) -> Result<(), BleError<Error<(), Stm32Wb5xError>>> {
+        if !self.ble_ready {
+            return Err(BleError::NotInitialized);
+        }
+
+        loop {
+            match self.with_coprocessor(|rc| rc.write_acl_data(packet))? {
+                Ok(()) => return Ok(()),
+                Err(nb::Error::WouldBlock) => STATE.tx_int.wait().await,
+                Err(nb::Error::Other(())) => return Err(BleError::EmptyError),
             }
-        } else {
-            Err(BleError::NotInitialized)
         }
     }
 
+    /// awaits the next ACL data packet received from CPU2
+    pub async fn receive_acl_data(
+        &mut self,
+    ) -> Result<AclData, BleError<Error<(), Stm32Wb5xError>>> {
+        loop {
+            let packet = self.with_coprocessor(|rc| {
+                rc.process_events();
+                rc.read_acl_data()
+            })?;
+
+            if let Some(packet) = packet {
+                return Ok(packet);
+            }
+
+            STATE.rx_int.wait().await;
+        }
+    }
+
+    /// awaits the next system event reported by CPU2
+    pub async fn receive_sys_event(
+        &mut self,
+    ) -> Result<SysEvent, BleError<Error<(), Stm32Wb5xError>>> {
+        let event = Self::receive_sys_event_helper().await;
+        if let SysEvent::C2Ready(firmware) = event {
+            self.firmware = firmware;
+            self.ble_ready = false;
+        }
+
+        Ok(event)
+    }
+
+    /// returns the signals raised by the IPCC RX and TX interrupt handlers, e.g. for a
+    /// [`Bridge`](crate::bridge::Bridge)
+    pub fn interrupt_signals() -> (
+        &'static Signal<CriticalSectionRawMutex, ()>,
+        &'static Signal<CriticalSectionRawMutex, ()>,
+    ) {
+        (&STATE.rx_int, &STATE.tx_int)
+    }
+
     /// returns `true` if there are some event(s) to be received
     pub fn has_events(&self) -> bool {
         STATE.rx_int.signaled() || self.deferred_events.peek().is_some()
     }
 
+    /// returns a snapshot of the BLE stack and mailbox counters
+    pub fn stats(&self) -> BleStats {
+        let (mbox, hci_buffer_high_water) =
+            with_rc(|rc| (rc.mbox_stats(), rc.hci_buffer_high_water() as u32))
+                .unwrap_or((MboxStats::default(), 0));
+
+        BleStats {
+            mbox,
+            hci_buffer_high_water,
+            deferred_events_high_water: COUNTERS.deferred_events_high_water.get(),
+            deferred_events_overflows: COUNTERS.deferred_events_overflows.get(),
+            commands_completed: COUNTERS.commands_completed.get(),
+            last_command_latency_us: COUNTERS.last_command_latency_us.get(),
+            max_command_latency_us: COUNTERS.max_command_latency_us.get(),
+            total_command_latency_us: COUNTERS.total_command_latency_us.get(),
+        }
+    }
+
+    /// resets all counters and high-water marks
+    pub fn reset_stats(&mut self) {
+        with_rc(|rc| rc.reset_stats());
+
+        COUNTERS.deferred_events_high_water.reset();
+        COUNTERS.deferred_events_overflows.reset();
+        COUNTERS.commands_completed.reset();
+        COUNTERS.last_command_latency_us.reset();
+        COUNTERS.max_command_latency_us.reset();
+        COUNTERS.total_command_latency_us.reset();
+    }
+
+    fn record_command_latency(sent_at: Instant) {
+        let latency = sent_at.elapsed().as_micros() as u32;
+
+        COUNTERS.commands_completed.increment();
+        COUNTERS.last_command_latency_us.set(latency);
+        COUNTERS.max_command_latency_us.record_max(latency);
+        COUNTERS.total_command_latency_us.add(latency);
+    }
+
+    /// takes a snapshot of the mailbox shared memory, e.g. to log it when CPU2 stops responding
+    pub fn mbox_snapshot(&self) -> Option<MboxSnapshot> {
+        with_rc(|rc| rc.mbox_snapshot())
+    }
+
+    /// returns the next command response that did not answer the command in flight and was
+    /// dropped, see [`CommandDiagnostic`]
+    pub fn read_diagnostic(&mut self) -> Option<CommandDiagnostic> {
+        with_rc(|rc| {
+            rc.process_events();
+            rc.read_diagnostic()
+        })?
+    }
+
+    /// runs `f` on the radio coprocessor, e.g. to send a command without awaiting its response.
+    ///
+    /// `f` runs in a critical section, which keeps the IPCC interrupt handlers away from the
+    /// coprocessor.
+    pub fn with_coprocessor<R>(
+        &mut self,
+        f: impl FnOnce(&mut Rc) -> R,
+    ) -> Result<R, BleError<Error<(), Stm32Wb5xError>>> {
+        with_rc(f).ok_or(BleError::NotInitialized)
+    }
+
+    /// sends a command and awaits its command complete or command status event
+    async fn command_helper(
+        queue: &mut HeaplessEvtQueue,
+        command: impl Fn(&mut Rc) -> nb::Result<(), ()>,
+    ) -> Result<Packet<Stm32Wb5xEvent>, BleError<Error<(), Stm32Wb5xError>>> {
+        // the command is held by `rc` until a credit is granted, a full queue makes us wait
+        loop {
+            match with_rc(&command).ok_or(BleError::NotInitialized)? {
+                Ok(()) => break,
+                Err(nb::Error::WouldBlock) => {
+                    STATE.rx_int.wait().await;
+
+                    // the credits granted by CPU2 only send the queued commands here
+                    with_rc(|rc| rc.process_events());
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let sent_at = Instant::now();
+        let response = Self::receive_event_helper(queue, Awaited::CommandResponse).await?;
+        Self::record_command_latency(sent_at);
+
+        Ok(response)
+    }
+
     async fn receive_event_helper(
         queue: &mut HeaplessEvtQueue,
-        rc: &mut Rc,
-        need_cmd_response: bool,
+        awaited: Awaited,
     ) -> nb::Result<Packet<Stm32Wb5xEvent>, Error<(), Stm32Wb5xError>> {
         loop {
-            let event = cortex_m::interrupt::free(|_| {
+            let event = with_rc(|rc| {
                 rc.process_events();
                 rc.read().ok()
-            });
+            })
+            .flatten();
 
-            // If the receiver is only interested in command response events,
-            // it will be an error to return the first event from the event queue since
+            // If the receiver is only interested in command response or procedure completion
+            // events, it will be an error to return the first event from the event queue since
             // it is not guaranteed that no events occurred in between of command execution and
             // response.
-            // Thus we defer all of the non-command-response events into the temporary queue before
-            // we get a command response event that will be returned.
-            if need_cmd_response {
+            // Thus we defer all of the other events into the temporary queue before we get the
+            // awaited event that will be returned.
+            if !matches!(awaited, Awaited::Any) {
                 if let Some(event) = event {
-                    if let Packet::Event(Event::CommandComplete(_)) = event {
+                    if awaited.matches(&event) {
                         return Ok(event);
                     } else {
                         // Defer the currently received event into temporary queue
                         // for it to be processed later
-                        queue.enqueue(event).unwrap();
+                        if queue.enqueue(event).is_err() {
+                            defmt::warn!("deferred event queue is full, dropping event");
+                            COUNTERS.deferred_events_overflows.increment();
+                        }
+                        COUNTERS
+                            .deferred_events_high_water
+                            .record_max(queue.len() as u32);
                     }
                 }
             } else {
-                let event = queue.dequeue().or(event);
+                // the deferred events were received before the fresh one, which goes behind them
+                let event = match (queue.dequeue(), event) {
+                    (Some(deferred), Some(event)) => {
+                        // dequeuing made room for it
+                        let _ = queue.enqueue(event);
+                        Some(deferred)
+                    }
+                    (deferred, event) => deferred.or(event),
+                };
+
                 if let Some(event) = event {
                     return Ok(event);
                 }
@@ -187,18 +820,60 @@ impl Ble {
         }
     }
 
-    unsafe fn on_tx_irq(_ctx: *mut ()) {
-        if let Some(rc) = RADIO_COPROCESSOR.as_mut() {
-            rc.handle_ipcc_tx();
+    /// waits for the `coprocessor ready` event and returns the firmware CPU2 runs
+    async fn receive_c2_ready() -> FirmwareKind {
+        loop {
+            match Self::receive_sys_event_helper().await {
+                SysEvent::C2Ready(firmware) => return firmware,
+                event => defmt::debug!("dropping {} received before `coprocessor ready`", event),
+            }
         }
+    }
 
-        STATE.tx_int.signal(());
+    async fn receive_sys_event_helper() -> SysEvent {
+        loop {
+            let event = with_rc(|rc| {
+                rc.process_events();
+                rc.read_sys_event()
+            })
+            .flatten();
+
+            if let Some(event) = event {
+                return event;
+            }
+
+            STATE.rx_int.wait().await;
+        }
     }
 
-    unsafe fn on_rx_irq(_ctx: *mut ()) {
-        if let Some(rc) = RADIO_COPROCESSOR.as_mut() {
-            rc.handle_ipcc_rx();
+    async fn receive_shci_response(opcode: u16) -> ShciResponse {
+        loop {
+            let response = with_rc(|rc| {
+                rc.process_events();
+                rc.take_shci_response()
+            })
+            .flatten();
+
+            match response {
+                Some(response) if response.opcode == opcode => return response,
+                Some(response) => {
+                    defmt::warn!("dropping response to SHCI command {:#06x}", response.opcode)
+                }
+                None => {}
+            }
+
+            STATE.tx_int.wait().await;
         }
+    }
+
+    fn on_tx_irq(_ctx: *mut ()) {
+        with_rc(|rc| rc.handle_ipcc_tx());
+
+        STATE.tx_int.signal(());
+    }
+
+    fn on_rx_irq(_ctx: *mut ()) {
+        with_rc(|rc| rc.handle_ipcc_rx());
 
         STATE.rx_int.signal(());
     }
@@ -214,5 +889,7 @@ impl Drop for Ble {
 
         STATE.rx_int.reset();
         STATE.tx_int.reset();
+
+        RADIO_COPROCESSOR.lock(|cell| cell.take());
     }
 }
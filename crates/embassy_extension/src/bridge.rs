@@ -0,0 +1,534 @@
+//! Transparent mode: a bridge between a host on a serial link and CPU2.
+//!
+//! A host such as BlueZ, STM32CubeMonitor-RF or a sniffer-assisted debugging setup drives the BLE
+//! stack directly, as with ST's `BLE_TransparentMode` application. The host sends H4 packets:
+//!
+//! - BLE commands and ACL data are forwarded to CPU2,
+//! - system commands are forwarded to CPU2, their response is sent back as a packet of kind
+//!   [`TlPacketType::SysRsp`],
+//! - local commands are answered by CPU1, see [`lhci`].
+//!
+//! Events and ACL data received from CPU2 are sent back to the host. A single command is in
+//! flight at a time: the next packet is only read once CPU2 answered the command, and the serial
+//! link applies back-pressure to the host in the meantime. If CPU2 answers another command, the
+//! bridge stops waiting and reports [`BridgeError::UnexpectedResponse`].
+//!
+//! ```ignore
+//! let (tx, rx) = uart.split();
+//! let (rx_int, tx_int) = Ble::interrupt_signals();
+//! let mut bridge = Bridge::new(&mut ble, rx, tx, rx_int, tx_int);
+//!
+//! bridge.run().await?;
+//! ```
+//!
+//! [`Bridge`] only depends on the [`Mailbox`] trait and on `embedded-io`, so that it can be
+//! exercised on the host with an in-memory serial pair and a mock mailbox. The mailbox of CPU2
+//! is only implemented when building for the target.
+
+use core::convert::Infallible;
+
+use bluetooth_hci::Controller;
+use embassy_futures::select::{select, select3, Either, Either3};
+use embassy_sync::{blocking_mutex::raw::RawMutex, signal::Signal};
+use embedded_io::asynch::{Read, Write};
+
+use crate::tl_mbox::{
+    consts::{TlPacketType, TL_BLEEVT_CC_OPCODE, TL_BLEEVT_CS_OPCODE},
+    lhci::{self, LhciError},
+};
+#[cfg(target_os = "none")]
+use crate::{ble::Ble, hci::RadioCoprocessor};
+
+/// Largest H4 packet: a command with 255 bytes of parameters.
+const H4_BUF_SIZE: usize = 4 + 255;
+
+/// Packet type, opcode and parameter length of a command.
+const H4_CMD_HEADER_SIZE: usize = 4;
+
+/// Packet type, handle and data length of an ACL packet.
+const H4_ACL_HEADER_SIZE: usize = 5;
+
+/// Packet type, event code and parameter length of an event.
+const H4_EVT_HEADER_SIZE: usize = 3;
+
+/// Side of the bridge that talks to CPU2, implemented by [`Ble`](crate::ble::Ble) and
+/// [`RadioCoprocessor`](crate::hci::RadioCoprocessor).
+pub trait Mailbox {
+    /// sends a BLE command, a system command or an ACL packet, starting with its H4 packet type
+    fn send(&mut self, packet: &[u8]) -> nb::Result<(), ()>;
+
+    /// moves the next event, system response or ACL packet received from CPU2 into `buf`,
+    /// starting with its H4 packet type, and returns its length
+    fn receive(&mut self, buf: &mut [u8]) -> Option<usize>;
+}
+
+impl<M: Mailbox> Mailbox for &mut M {
+    fn send(&mut self, packet: &[u8]) -> nb::Result<(), ()> {
+        (**self).send(packet)
+    }
+
+    fn receive(&mut self, buf: &mut [u8]) -> Option<usize> {
+        (**self).receive(buf)
+    }
+}
+
+#[cfg(target_os = "none")]
+impl<'buf, const N: usize> Mailbox for RadioCoprocessor<'buf, N> {
+    fn send(&mut self, packet: &[u8]) -> nb::Result<(), ()> {
+        let header_len = match packet.first().map(|&kind| TlPacketType::try_from(kind)) {
+            Some(Ok(TlPacketType::AclData)) => H4_ACL_HEADER_SIZE,
+            Some(Ok(TlPacketType::BleCmd | TlPacketType::SysCmd)) => H4_CMD_HEADER_SIZE,
+            _ => return Err(nb::Error::Other(())),
+        };
+
+        self.write(&packet[..header_len], &packet[header_len..])
+    }
+
+    fn receive(&mut self, buf: &mut [u8]) -> Option<usize> {
+        self.process_events();
+
+        if let Some(packet) = self.read_acl_data() {
+            return packet.write(buf).ok();
+        }
+
+        if let Some(len) = self.read_sys_response(buf) {
+            return Some(len);
+        }
+
+        let len = H4_EVT_HEADER_SIZE + self.peek(2).ok()? as usize;
+        self.read_into(buf.get_mut(..len)?).ok()?;
+
+        Some(len)
+    }
+}
+
+#[cfg(target_os = "none")]
+impl Mailbox for Ble {
+    fn send(&mut self, packet: &[u8]) -> nb::Result<(), ()> {
+        self.with_coprocessor(|rc| rc.send(packet))
+            .unwrap_or(Err(nb::Error::Other(())))
+    }
+
+    fn receive(&mut self, buf: &mut [u8]) -> Option<usize> {
+        self.with_coprocessor(|rc| rc.receive(buf)).ok()?
+    }
+}
+
+/// Errors that stop the bridge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BridgeError<R, W> {
+    /// reading from the host failed
+    Read(R),
+    /// writing to the host failed
+    Write(W),
+    /// the host closed the serial link
+    EndOfStream,
+    /// the host sent a packet type the bridge does not forward, the H4 framing is lost
+    BadPacketType(u8),
+    /// the host sent a packet larger than the mailbox buffers
+    PacketTooLong(usize),
+    /// the mailbox rejected a packet
+    Mailbox,
+    /// a local command could not be answered
+    Local(LhciError),
+    /// CPU2 answered another command than the one in flight, which is no longer waited for.
+    /// Includes the opcode of the command in flight and the answered opcode.
+    UnexpectedResponse(u16, u16),
+}
+
+/// Forwards H4 packets between a host and CPU2.
+pub struct Bridge<'a, M, R, W, RM: RawMutex> {
+    mailbox: M,
+    reader: R,
+    writer: W,
+    rx_int: &'a Signal<RM, ()>,
+    tx_int: &'a Signal<RM, ()>,
+    input: [u8; H4_BUF_SIZE],
+    input_len: usize,
+    output: [u8; H4_BUF_SIZE],
+    /// opcode of the command sent to CPU2 whose response has not been sent back to the host yet
+    pending: Option<u16>,
+    acl_blocked: bool,
+}
+
+impl<'a, M, R, W, RM> Bridge<'a, M, R, W, RM>
+where
+    M: Mailbox,
+    R: Read,
+    W: Write,
+    RM: RawMutex,
+{
+    /// creates a bridge reading the host packets from `reader` and writing the answers to
+    /// `writer`.
+    ///
+    /// `rx_int` and `tx_int` are signaled by the IPCC RX and TX interrupt handlers.
+    pub fn new(
+        mailbox: M,
+        reader: R,
+        writer: W,
+        rx_int: &'a Signal<RM, ()>,
+        tx_int: &'a Signal<RM, ()>,
+    ) -> Self {
+        Self {
+            mailbox,
+            reader,
+            writer,
+            rx_int,
+            tx_int,
+            input: [0; H4_BUF_SIZE],
+            input_len: 0,
+            output: [0; H4_BUF_SIZE],
+            pending: None,
+            acl_blocked: false,
+        }
+    }
+
+    /// forwards packets until an error occurs
+    pub async fn run(&mut self) -> Result<Infallible, BridgeError<R::Error, W::Error>> {
+        loop {
+            self.step().await?;
+        }
+    }
+
+    /// forwards the packets that are ready, then waits for the host or for CPU2.
+    ///
+    /// Reading from the host is cancelled when CPU2 has something to report, `reader` has to keep
+    /// the bytes of a cancelled read.
+    pub async fn step(&mut self) -> Result<(), BridgeError<R::Error, W::Error>> {
+        self.forward_to_host().await?;
+
+        if self.pending.is_none() && !self.acl_blocked {
+            if let Some(len) = self.next_packet()? {
+                return self.forward_to_coprocessor(len).await;
+            }
+        }
+
+        if self.pending.is_some() || self.acl_blocked {
+            match select(self.rx_int.wait(), self.tx_int.wait()).await {
+                Either::First(()) => {}
+                Either::Second(()) => self.acl_blocked = false,
+            }
+
+            return Ok(());
+        }
+
+        let read = self.reader.read(&mut self.input[self.input_len..]);
+        match select3(read, self.rx_int.wait(), self.tx_int.wait()).await {
+            Either3::First(Ok(0)) => Err(BridgeError::EndOfStream),
+            Either3::First(Ok(n)) => {
+                self.input_len += n;
+                Ok(())
+            }
+            Either3::First(Err(e)) => Err(BridgeError::Read(e)),
+            Either3::Second(()) | Either3::Third(()) => Ok(()),
+        }
+    }
+
+    /// sends the events, ACL packets and system responses received from CPU2 to the host
+    async fn forward_to_host(&mut self) -> Result<(), BridgeError<R::Error, W::Error>> {
+        while let Some(len) = self.mailbox.receive(&mut self.output) {
+            let answered = answered_opcode(&self.output[..len]);
+
+            self.writer
+                .write_all(&self.output[..len])
+                .await
+                .map_err(BridgeError::Write)?;
+
+            if let (Some(pending), Some(answered)) = (self.pending, answered) {
+                self.pending = None;
+
+                if answered != pending {
+                    return Err(BridgeError::UnexpectedResponse(pending, answered));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// returns the length of the packet at the start of the input once it is complete
+    fn next_packet(&self) -> Result<Option<usize>, BridgeError<R::Error, W::Error>> {
+        let input = &self.input[..self.input_len];
+
+        let len = match input.first().map(|&kind| TlPacketType::try_from(kind)) {
+            None => return Ok(None),
+            Some(Ok(TlPacketType::BleCmd | TlPacketType::SysCmd | TlPacketType::LocCmd)) => {
+                match input.get(3) {
+                    Some(&len) => H4_CMD_HEADER_SIZE + len as usize,
+                    None => return Ok(None),
+                }
+            }
+            Some(Ok(TlPacketType::AclData)) => match input.get(3..5) {
+                Some(len) => H4_ACL_HEADER_SIZE + u16::from_le_bytes([len[0], len[1]]) as usize,
+                None => return Ok(None),
+            },
+            _ => return Err(BridgeError::BadPacketType(input[0])),
+        };
+
+        if len > H4_BUF_SIZE {
+            return Err(BridgeError::PacketTooLong(len));
+        }
+
+        Ok((input.len() >= len).then_some(len))
+    }
+
+    /// handles the packet of `len` bytes at the start of the input
+    async fn forward_to_coprocessor(
+        &mut self,
+        len: usize,
+    ) -> Result<(), BridgeError<R::Error, W::Error>> {
+        let packet = &self.input[..len];
+        let opcode = u16::from_le_bytes([packet[1], packet[2]]);
+
+        match TlPacketType::try_from(packet[0]) {
+            Ok(TlPacketType::LocCmd) => {
+                // the host is trusted with the registers of CPU1, as in ST's transparent mode
+                let response_len = unsafe { lhci::process(packet, &mut self.output) }
+                    .map_err(BridgeError::Local)?;
+
+                self.writer
+                    .write_all(&self.output[..response_len])
+                    .await
+                    .map_err(BridgeError::Write)?;
+            }
+            Ok(kind) => match self.mailbox.send(packet) {
+                Ok(()) => {
+                    self.pending = match kind {
+                        TlPacketType::BleCmd | TlPacketType::SysCmd => Some(opcode),
+                        _ => None,
+                    };
+                }
+                Err(nb::Error::WouldBlock) => {
+                    // CPU2 still holds the ACL buffer, the packet stays in the input
+                    self.acl_blocked = true;
+                    return Ok(());
+                }
+                Err(nb::Error::Other(())) => return Err(BridgeError::Mailbox),
+            },
+            Err(()) => return Err(BridgeError::BadPacketType(packet[0])),
+        }
+
+        self.input.copy_within(len..self.input_len, 0);
+        self.input_len -= len;
+
+        Ok(())
+    }
+}
+
+/// returns the opcode answered by a command complete or command status event, or by the
+/// response to a system command. The events that only return credits answer no command.
+fn answered_opcode(packet: &[u8]) -> Option<u16> {
+    let kind = packet.first()?;
+    if *kind != TlPacketType::BleEvt as u8 && *kind != TlPacketType::SysRsp as u8 {
+        return None;
+    }
+
+    let opcode = match packet.get(1)? {
+        &TL_BLEEVT_CC_OPCODE => packet.get(4..6)?,
+        &TL_BLEEVT_CS_OPCODE => packet.get(5..7)?,
+        _ => return None,
+    };
+
+    match u16::from_le_bytes([opcode[0], opcode[1]]) {
+        0 => None,
+        opcode => Some(opcode),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{collections::VecDeque, vec, vec::Vec};
+
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embedded_io::Io;
+
+    use super::*;
+
+    /// serial link whose host sends `chunks`, one per read, and then nothing
+    #[derive(Default)]
+    struct Serial {
+        chunks: VecDeque<Vec<u8>>,
+        written: Vec<u8>,
+    }
+
+    impl Io for Serial {
+        type Error = Infallible;
+    }
+
+    impl Read for Serial {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            let mut chunk = match self.chunks.pop_front() {
+                Some(chunk) => chunk,
+                None => core::future::pending().await,
+            };
+
+            let len = chunk.len().min(buf.len());
+            buf[..len].copy_from_slice(&chunk[..len]);
+            if len < chunk.len() {
+                self.chunks.push_front(chunk.split_off(len));
+            }
+
+            Ok(len)
+        }
+    }
+
+    impl Write for Serial {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    /// mailbox that keeps the sent packets and returns `received` in order
+    #[derive(Default)]
+    struct MockMailbox {
+        sent: Vec<Vec<u8>>,
+        received: VecDeque<Vec<u8>>,
+        /// number of the next packets rejected because CPU2 still holds the buffer
+        busy: usize,
+    }
+
+    impl Mailbox for MockMailbox {
+        fn send(&mut self, packet: &[u8]) -> nb::Result<(), ()> {
+            if self.busy > 0 {
+                self.busy -= 1;
+                return Err(nb::Error::WouldBlock);
+            }
+
+            self.sent.push(packet.to_vec());
+            Ok(())
+        }
+
+        fn receive(&mut self, buf: &mut [u8]) -> Option<usize> {
+            let packet = self.received.pop_front()?;
+            buf[..packet.len()].copy_from_slice(&packet);
+
+            Some(packet.len())
+        }
+    }
+
+    type TestBridge<'a> = Bridge<'a, MockMailbox, Serial, Serial, NoopRawMutex>;
+
+    const RESET: [u8; 4] = [0x01, 0x03, 0x0c, 0x00];
+    const RESET_COMPLETE: [u8; 7] = [0x04, 0x0e, 0x04, 0x01, 0x03, 0x0c, 0x00];
+
+    fn bridge<'a>(
+        chunks: &[&[u8]],
+        rx_int: &'a Signal<NoopRawMutex, ()>,
+        tx_int: &'a Signal<NoopRawMutex, ()>,
+    ) -> TestBridge<'a> {
+        let reader = Serial {
+            chunks: chunks.iter().map(|chunk| chunk.to_vec()).collect(),
+            ..Serial::default()
+        };
+
+        Bridge::new(MockMailbox::default(), reader, Serial::default(), rx_int, tx_int)
+    }
+
+    #[test]
+    fn command_split_across_reads() {
+        let (rx_int, tx_int) = (Signal::new(), Signal::new());
+        let mut bridge = bridge(&[&RESET[..1], &RESET[1..3], &RESET[3..]], &rx_int, &tx_int);
+
+        for _ in 0..3 {
+            block_on(bridge.step()).unwrap();
+            assert!(bridge.mailbox.sent.is_empty());
+        }
+
+        block_on(bridge.step()).unwrap();
+        assert_eq!(bridge.mailbox.sent, vec![RESET.to_vec()]);
+        assert_eq!(bridge.pending, Some(0x0c03));
+
+        bridge.mailbox.received.push_back(RESET_COMPLETE.to_vec());
+        rx_int.signal(());
+        block_on(bridge.step()).unwrap();
+        assert_eq!(bridge.writer.written, RESET_COMPLETE);
+        assert_eq!(bridge.pending, None);
+    }
+
+    #[test]
+    fn local_command_is_answered_by_cpu1() {
+        let (rx_int, tx_int) = (Signal::new(), Signal::new());
+        let mut bridge = bridge(&[&[0x20, 0x6f, 0xfd, 0x00]], &rx_int, &tx_int);
+
+        block_on(bridge.step()).unwrap();
+        block_on(bridge.step()).unwrap();
+
+        assert!(bridge.mailbox.sent.is_empty());
+        assert_eq!(bridge.pending, None);
+        // unknown command status
+        assert_eq!(bridge.writer.written, [0x21, 0x0e, 0x04, 0x01, 0x6f, 0xfd, 0x01]);
+    }
+
+    #[test]
+    fn acl_data_is_retried_once_cpu2_releases_the_buffer() {
+        let acl = [0x02, 0x01, 0x00, 0x02, 0x00, 0xaa, 0xbb];
+        let (rx_int, tx_int) = (Signal::new(), Signal::new());
+        let mut bridge = bridge(&[&acl], &rx_int, &tx_int);
+        bridge.mailbox.busy = 1;
+
+        block_on(bridge.step()).unwrap();
+        block_on(bridge.step()).unwrap();
+        assert!(bridge.acl_blocked);
+        assert!(bridge.mailbox.sent.is_empty());
+
+        tx_int.signal(());
+        block_on(bridge.step()).unwrap();
+        assert!(!bridge.acl_blocked);
+
+        block_on(bridge.step()).unwrap();
+        assert_eq!(bridge.mailbox.sent, vec![acl.to_vec()]);
+        assert_eq!(bridge.input_len, 0);
+        assert_eq!(bridge.pending, None);
+    }
+
+    #[test]
+    fn system_command_round_trip() {
+        let command = [0x10, 0x66, 0xfc, 0x00];
+        let response = [0x11, 0x0e, 0x04, 0x01, 0x66, 0xfc, 0x00];
+        let (rx_int, tx_int) = (Signal::new(), Signal::new());
+        let mut bridge = bridge(&[&command, &RESET], &rx_int, &tx_int);
+
+        block_on(bridge.step()).unwrap();
+        block_on(bridge.step()).unwrap();
+        assert_eq!(bridge.mailbox.sent, vec![command.to_vec()]);
+        assert_eq!(bridge.pending, Some(0xfc66));
+
+        // the next command waits for the response
+        rx_int.signal(());
+        block_on(bridge.step()).unwrap();
+        assert_eq!(bridge.mailbox.sent.len(), 1);
+
+        bridge.mailbox.received.push_back(response.to_vec());
+        rx_int.signal(());
+        block_on(bridge.step()).unwrap();
+        assert_eq!(bridge.writer.written, response);
+        assert_eq!(bridge.pending, None);
+
+        block_on(bridge.step()).unwrap();
+        block_on(bridge.step()).unwrap();
+        assert_eq!(bridge.mailbox.sent, vec![command.to_vec(), RESET.to_vec()]);
+    }
+
+    #[test]
+    fn response_to_another_command_stops_the_wait() {
+        let (rx_int, tx_int) = (Signal::new(), Signal::new());
+        let mut bridge = bridge(&[&RESET], &rx_int, &tx_int);
+
+        block_on(bridge.step()).unwrap();
+        block_on(bridge.step()).unwrap();
+
+        let other = [0x04, 0x0e, 0x04, 0x01, 0x01, 0x10, 0x00];
+        bridge.mailbox.received.push_back(other.to_vec());
+        assert_eq!(block_on(bridge.step()), Err(BridgeError::UnexpectedResponse(0x0c03, 0x1001)));
+        assert_eq!(bridge.writer.written, other);
+        assert_eq!(bridge.pending, None);
+    }
+}
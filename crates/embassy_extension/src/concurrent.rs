@@ -0,0 +1,84 @@
+//! Concurrent BLE + Thread operation.
+//!
+//! ST's concurrent wireless firmwares run the BLE and the Thread stacks on CPU2 and share the
+//! radio between them. Both stacks use the same mailbox: the BLE stack is started by
+//! [`Ble::init`], the Thread stack by [`Concurrent::thread_init`]. With the static concurrent
+//! firmware, CPU1 decides which stack has the radio.
+//!
+//! Only the radio arbitration is supported: the Thread stack is started, but there is no
+//! OpenThread command path and its notifications are dropped, see [`Thread`].
+//!
+//! ```ignore
+//! let mut concurrent = ble.concurrent();
+//! concurrent.thread_init().await?;
+//!
+//! // run Thread in between BLE connection events that are at least 10 ms apart
+//! if let Some(window) = concurrent.thread_window(Duration::from_millis(10)).await? {
+//!     // ... the Thread stack has the radio for up to `window`
+//!     concurrent.set_mode(ConcurrentMode::Ble).await?;
+//! }
+//! ```
+//!
+//! [`Thread`]: crate::tl_mbox::thread::Thread
+
+use embassy_time::Duration;
+
+use crate::{
+    ble::{Ble, BleError, BleTransportLayerError},
+    tl_mbox::shci::ConcurrentMode,
+};
+
+/// handle for running the Thread stack next to the BLE stack, see [`Ble::concurrent`]
+pub struct Concurrent<'a> {
+    ble: &'a mut Ble,
+}
+
+impl<'a> Concurrent<'a> {
+    pub(crate) fn new(ble: &'a mut Ble) -> Self {
+        Self { ble }
+    }
+
+    /// registers the Thread buffers in the mailbox and starts the Thread stack, without a way
+    /// to send it OpenThread commands
+    pub async fn thread_init(&mut self) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble.with_coprocessor(|rc| rc.init_thread())?;
+
+        self.ble.shci().c2_thread_init().await
+    }
+
+    /// gives the radio priority to `mode`
+    pub async fn set_mode(
+        &mut self,
+        mode: ConcurrentMode,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble.shci().c2_concurrent_set_mode(mode).await
+    }
+
+    /// returns the time until the next BLE radio event
+    pub async fn next_ble_event_time(
+        &mut self,
+    ) -> Result<Duration, BleError<BleTransportLayerError>> {
+        let us = self.ble.shci().c2_get_next_ble_evt_time().await?;
+
+        Ok(Duration::from_micros(us as u64))
+    }
+
+    /// gives the radio to the Thread stack if the next BLE radio event is at least `min_window`
+    /// away.
+    ///
+    /// Returns the time until the next BLE radio event if the Thread stack got the radio. The
+    /// radio has to be given back with [`Concurrent::set_mode`] before that time.
+    pub async fn thread_window(
+        &mut self,
+        min_window: Duration,
+    ) -> Result<Option<Duration>, BleError<BleTransportLayerError>> {
+        let window = self.next_ble_event_time().await?;
+        if window < min_window {
+            return Ok(None);
+        }
+
+        self.set_mode(ConcurrentMode::Thread).await?;
+
+        Ok(Some(window))
+    }
+}
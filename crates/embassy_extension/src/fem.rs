@@ -0,0 +1,233 @@
+//! External front-end module (PA/LNA) control.
+//!
+//! Boards with an external power amplifier enable it with a GPIO around the radio activities.
+//! The pin is driven either:
+//!
+//! - by CPU2, which is told the pin with
+//!   [`Shci::c2_extpa_config`](crate::shci::Shci::c2_extpa_config) and toggles it around each
+//!   transmission. CPU1 only configures the pin as an output,
+//! - by CPU1, from the [end of radio activity](Stm32Wb5xEvent::HalEndOfRadioActivity) events
+//!   that give the next radio activity of CPU2. This is only a coarse on/off: the PA is enabled
+//!   as long as CPU2 has a radio activity planned, i.e. all the time while advertising or
+//!   connected, and not only around the radio events.
+//!
+//! The gain of the PA adds to the output power of the radio, [`Fem::set_tx_power`] selects the
+//! [`PowerLevel`] that gives the requested output power at the antenna:
+//!
+//! ```ignore
+//! let mut fem = Fem::new(pin, FemConfig {
+//!     port: GpioPort::B,
+//!     pin: PinNumber::new(0).unwrap(),
+//!     polarity: Polarity::ActiveHigh,
+//!     control: FemControl::Cpu1,
+//!     pa_gain_db: 10.0,
+//! })?;
+//! fem.configure(&mut ble).await?;
+//! fem.set_tx_power(&mut ble, 14.0).await?;
+//!
+//! loop {
+//!     if let Packet::Event(Event::Vendor(event)) = ble.receive_event().await? {
+//!         fem.process_event(&event)?;
+//!     }
+//! }
+//! ```
+
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::{
+    ble::{Ble, BleError, BleTransportLayerError},
+    hci::{
+        command::hal::{PowerLevel, RadioActivityFlags},
+        event::{command::LinkState, Stm32Wb5xEvent},
+    },
+    tl_mbox::shci::ShciExtpaConfigParam,
+};
+
+/// GPIO ports of the STM32WB5x.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum GpioPort {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+}
+
+impl GpioPort {
+    /// base address of the port registers
+    fn address(self) -> u32 {
+        match self {
+            GpioPort::A => 0x4800_0000,
+            GpioPort::B => 0x4800_0400,
+            GpioPort::C => 0x4800_0800,
+            GpioPort::D => 0x4800_0c00,
+            GpioPort::E => 0x4800_1000,
+            GpioPort::H => 0x4800_1c00,
+        }
+    }
+}
+
+/// Number of a pin in its GPIO port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct PinNumber(u8);
+
+impl PinNumber {
+    /// returns the pin `number`, or `None` if it is not in `0..=15`
+    pub const fn new(number: u8) -> Option<Self> {
+        if number < 16 {
+            Some(Self(number))
+        } else {
+            None
+        }
+    }
+
+    /// returns the number of the pin
+    pub const fn number(self) -> u8 {
+        self.0
+    }
+
+    /// returns the mask of the pin in its port
+    pub const fn mask(self) -> u16 {
+        1 << self.0
+    }
+}
+
+/// Level of the control pin that enables the external PA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Polarity {
+    ActiveLow,
+    ActiveHigh,
+}
+
+/// Core that drives the control pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum FemControl {
+    /// CPU2 toggles the pin around each transmission
+    Cpu2,
+    /// CPU1 enables the PA while CPU2 has a radio activity planned, see [`Fem::process_event`]
+    Cpu1,
+}
+
+/// Description of the external front-end module.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct FemConfig {
+    /// port of the control pin
+    pub port: GpioPort,
+    /// number of the control pin in its port
+    pub pin: PinNumber,
+    /// level of the control pin that enables the PA
+    pub polarity: Polarity,
+    /// core that drives the control pin
+    pub control: FemControl,
+    /// gain of the PA, in dB
+    pub pa_gain_db: f32,
+}
+
+impl FemConfig {
+    /// returns the `SHCI_C2_ExtpaConfig` parameters, CPU2 only drives the pin with
+    /// [`FemControl::Cpu2`]
+    pub fn c2_param(&self) -> ShciExtpaConfigParam {
+        ShciExtpaConfigParam {
+            ctrl_port: self.port.address(),
+            ctrl_pin: self.pin.mask(),
+            ctrl: (self.polarity == Polarity::ActiveHigh) as u8,
+            state: (self.control == FemControl::Cpu2) as u8,
+        }
+    }
+
+    /// returns the output power at the antenna with the radio set to `level`, in dBm
+    pub fn output_power(&self, level: PowerLevel) -> f32 {
+        level.dbm() + self.pa_gain_db
+    }
+
+    /// returns the highest power level whose output power at the antenna does not exceed `dbm`
+    pub fn power_level(&self, dbm: f32) -> PowerLevel {
+        PowerLevel::at_most(dbm - self.pa_gain_db)
+    }
+}
+
+/// Drives the control pin of an external front-end module.
+pub struct Fem<P> {
+    pin: P,
+    config: FemConfig,
+}
+
+impl<P: OutputPin> Fem<P> {
+    /// takes the control pin, which is left disabled
+    pub fn new(pin: P, config: FemConfig) -> Result<Self, P::Error> {
+        let mut fem = Self { pin, config };
+        fem.set_enabled(false)?;
+
+        Ok(fem)
+    }
+
+    /// returns the description of the front-end module
+    pub fn config(&self) -> &FemConfig {
+        &self.config
+    }
+
+    /// tells CPU2 which core drives the control pin. With [`FemControl::Cpu1`], CPU2 also
+    /// reports the end of its radio activities.
+    pub async fn configure(
+        &mut self,
+        ble: &mut Ble,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        ble.shci().c2_extpa_config(self.config.c2_param()).await?;
+
+        if self.config.control == FemControl::Cpu1 {
+            ble.hal()
+                .set_radio_activity_mask(RadioActivityFlags::all())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// sets the power level that gives at most `dbm` at the antenna and returns it, a rejected
+    /// level gives [`BleError::CommandFailed`]
+    pub async fn set_tx_power(
+        &mut self,
+        ble: &mut Ble,
+        dbm: f32,
+    ) -> Result<PowerLevel, BleError<BleTransportLayerError>> {
+        let level = self.config.power_level(dbm);
+        ble.hal().set_tx_power_level(level).await?;
+
+        Ok(level)
+    }
+
+    /// enables the PA if `event` reports a next radio activity, disables it once the radio goes
+    /// idle.
+    ///
+    /// The start time of the next activity is not used, the PA stays enabled in between the radio
+    /// events. Returns `true` if the event was an end of radio activity event. Does nothing when
+    /// CPU2 drives the pin.
+    pub fn process_event(&mut self, event: &Stm32Wb5xEvent) -> Result<bool, P::Error> {
+        match event {
+            Stm32Wb5xEvent::HalEndOfRadioActivity(activity) => {
+                if self.config.control == FemControl::Cpu1 {
+                    self.set_enabled(activity.next_state != LinkState::Idle)?;
+                }
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// enables or disables the PA
+    pub fn set_enabled(&mut self, enabled: bool) -> Result<(), P::Error> {
+        if enabled == (self.config.polarity == Polarity::ActiveHigh) {
+            self.pin.set_high()
+        } else {
+            self.pin.set_low()
+        }
+    }
+
+    /// disables the PA and releases the control pin
+    pub fn release(mut self) -> Result<P, P::Error> {
+        self.set_enabled(false)?;
+
+        Ok(self.pin)
+    }
+}
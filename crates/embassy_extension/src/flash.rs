@@ -0,0 +1,116 @@
+//! Flash operations of CPU1 while CPU2 is running.
+//!
+//! CPU1 and CPU2 share the flash and a flash operation stalls both cores. CPU2 has
+//! timing-critical windows (e.g. radio events) during which it must not be stalled, so every
+//! erase or program operation of CPU1 has to follow ST's handshake:
+//!
+//! - CPU2 is told to signal its timing-critical windows with the
+//!   [`BlockFlashReqByCpu2`](SemaphoreId::BlockFlashReqByCpu2) semaphore,
+//! - CPU2 is told before and after a sequence of erase operations, so that it schedules its
+//!   radio activity around them,
+//! - each single erase or program operation is performed while CPU1 holds the
+//!   [`Flash`](SemaphoreId::Flash) semaphore, with interrupts disabled and only after checking
+//!   that CPU2 does not hold [`BlockFlashReqByCpu2`](SemaphoreId::BlockFlashReqByCpu2). CPU1
+//!   never takes that semaphore itself.
+//!
+//! [`FlashGuard`] performs this handshake around the operations of any flash driver, awaiting
+//! the semaphores with [`Hsem`] instead of spinning.
+
+use crate::{
+    ble::{Ble, BleError, BleTransportLayerError},
+    hsem::{self, Hsem, SemaphoreId},
+    tl_mbox::shci::FlashActivityControl,
+};
+
+/// Errors that may occur during a guarded flash operation.
+#[derive(Debug)]
+pub enum FlashGuardError<E> {
+    /// CPU2 rejected the handshake.
+    Ble(BleError<BleTransportLayerError>),
+
+    /// The flash operation failed.
+    Flash(E),
+}
+
+impl<E> From<BleError<BleTransportLayerError>> for FlashGuardError<E> {
+    fn from(e: BleError<BleTransportLayerError>) -> Self {
+        FlashGuardError::Ble(e)
+    }
+}
+
+/// handle for erasing and programming the flash while CPU2 is running, see [`Ble::flash_guard`]
+pub struct FlashGuard<'a> {
+    ble: &'a mut Ble,
+    hsem: &'a Hsem,
+}
+
+impl<'a> FlashGuard<'a> {
+    /// configures CPU2 to signal its timing-critical windows with a semaphore
+    pub(crate) async fn new(
+        ble: &'a mut Ble,
+        hsem: &'a Hsem,
+    ) -> Result<FlashGuard<'a>, BleError<BleTransportLayerError>> {
+        ble.shci()
+            .c2_set_flash_activity_control(FlashActivityControl::Sem7)
+            .await?;
+
+        Ok(Self { ble, hsem })
+    }
+
+    /// erases the flash page by page.
+    ///
+    /// `erase_page` is called once for each page of `pages`, between two timing-critical windows
+    /// of CPU2.
+    pub async fn erase<E>(
+        &mut self,
+        pages: impl IntoIterator<Item = u32>,
+        mut erase_page: impl FnMut(u32) -> Result<(), E>,
+    ) -> Result<(), FlashGuardError<E>> {
+        self.ble.shci().c2_flash_erase_activity(true).await?;
+
+        let mut result = Ok(());
+        for page in pages {
+            result = single_operation(self.hsem, || erase_page(page)).await;
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.ble.shci().c2_flash_erase_activity(false).await?;
+
+        result.map_err(FlashGuardError::Flash)
+    }
+
+    /// programs the flash, `program` should write a small amount of data (e.g. a double word)
+    /// as it is called between two timing-critical windows of CPU2
+    pub async fn program<R>(&mut self, program: impl FnOnce() -> R) -> R {
+        single_operation(self.hsem, program).await
+    }
+}
+
+/// performs a single flash operation while CPU2 allows it
+async fn single_operation<R>(hsem: &Hsem, operation: impl FnOnce() -> R) -> R {
+    let mut operation = Some(operation);
+
+    loop {
+        // CPU2 holds the semaphore during its timing-critical windows
+        hsem.wait_released(SemaphoreId::BlockFlashReqByCpu2).await;
+        hsem.lock(SemaphoreId::Flash).await;
+
+        // CPU2 may have entered a timing-critical window since, check again with interrupts
+        // disabled so that the operation starts before CPU1 can be preempted
+        let result = cortex_m::interrupt::free(|_| {
+            if hsem::is_locked(SemaphoreId::BlockFlashReqByCpu2) {
+                None
+            } else {
+                operation.take().map(|operation| operation())
+            }
+        });
+
+        hsem::unlock(SemaphoreId::Flash);
+
+        if let Some(result) = result {
+            return result;
+        }
+    }
+}
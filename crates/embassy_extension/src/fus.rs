@@ -0,0 +1,271 @@
+//! Firmware Upgrade Service (FUS) of the STM32WB5x radio coprocessor.
+//!
+//! The FUS runs on CPU2 instead of the wireless firmware and manages the wireless binary and the
+//! keys stored in CPU2's secure flash. A wireless stack update is orchestrated by CPU1:
+//!
+//! 1. while the wireless firmware runs, [`Fus::get_state`] fails with
+//!    [`ShciStatus::FusCmdNotSupported`]; a second call restarts CPU2 into the FUS,
+//! 2. wait for CPU2 to report [`FirmwareKind::Fus`] with [`Ble::wait_coprocessor_ready`],
+//! 3. write the encrypted binary to flash and call [`Fus::fw_upgrade`], CPU2 restarts several
+//!    times while the binary is installed,
+//! 4. poll [`Fus::get_state`] until the FUS is [`FusState::Idle`] again,
+//! 5. call [`Fus::start_ws`] and wait for CPU2 to report [`FirmwareKind::Wireless`], then start
+//!    the BLE stack with [`Ble::start_ble`].
+
+use crate::{
+    ble::{Ble, BleError, BleTransportLayerError},
+    hci::event::FirmwareKind,
+    tl_mbox::shci::{ShciOpcode, ShciResponse, ShciStatus},
+};
+
+/// Maximum size of the authentication key, in bytes.
+pub const FUS_AUTH_KEY_MAX_SIZE: usize = 64;
+
+/// Maximum size of a user key, in bytes (a 32 bytes key and its 16 bytes IV).
+pub const FUS_USR_KEY_MAX_SIZE: usize = 48;
+
+/// State of the FUS as returned by [`Fus::get_state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum FusState {
+    Idle,
+    /// a wireless firmware upgrade is ongoing, includes the sub-state
+    FwUpgradeOngoing(u8),
+    /// a FUS upgrade is ongoing, includes the sub-state
+    FusUpgradeOngoing(u8),
+    /// a service (e.g. key management) is ongoing, includes the sub-state
+    ServiceOngoing(u8),
+    Error,
+    Unknown(u8),
+}
+
+impl From<u8> for FusState {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => FusState::Idle,
+            0x10..=0x1f => FusState::FwUpgradeOngoing(value),
+            0x20..=0x2f => FusState::FusUpgradeOngoing(value),
+            0x30..=0x3f => FusState::ServiceOngoing(value),
+            0xff => FusState::Error,
+            other => FusState::Unknown(other),
+        }
+    }
+}
+
+/// Error reported by the FUS along with its state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum FusError {
+    NoError,
+    ImageNotFound,
+    ImageCorrupt,
+    ImageNotAuthentic,
+    NotEnoughSpace,
+    ImageUserAbort,
+    ImageEraseError,
+    ImageWriteError,
+    AuthTagStNotFound,
+    AuthTagCustNotFound,
+    AuthKeyLocked,
+    FwRollbackError,
+    StateNotRunning,
+    Unknown(u8),
+}
+
+impl From<u8> for FusError {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => FusError::NoError,
+            0x01 => FusError::ImageNotFound,
+            0x02 => FusError::ImageCorrupt,
+            0x03 => FusError::ImageNotAuthentic,
+            0x04 => FusError::NotEnoughSpace,
+            0x05 => FusError::ImageUserAbort,
+            0x06 => FusError::ImageEraseError,
+            0x07 => FusError::ImageWriteError,
+            0x08 => FusError::AuthTagStNotFound,
+            0x09 => FusError::AuthTagCustNotFound,
+            0x0a => FusError::AuthKeyLocked,
+            0x11 => FusError::FwRollbackError,
+            0xfe => FusError::StateNotRunning,
+            other => FusError::Unknown(other),
+        }
+    }
+}
+
+/// Kind of a user key stored by [`Fus::store_usr_key`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum FusKeyType {
+    None = 0x00,
+    Simple = 0x01,
+    Master = 0x02,
+    Encrypted = 0x03,
+}
+
+/// Response to [`Fus::get_state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct FusStatus {
+    pub state: FusState,
+    pub error: FusError,
+}
+
+/// handle for sending FUS commands, see [`Ble::fus`]
+pub struct Fus<'a> {
+    ble: &'a mut Ble,
+}
+
+impl<'a> Fus<'a> {
+    pub(crate) fn new(ble: &'a mut Ble) -> Self {
+        Self { ble }
+    }
+
+    /// sends a FUS command and fails if CPU2 did not report success
+    async fn command(
+        &mut self,
+        opcode: ShciOpcode,
+        payload: &[u8],
+    ) -> Result<ShciResponse, BleError<BleTransportLayerError>> {
+        let response = self
+            .ble
+            .perform_shci_command(opcode as u16, payload)
+            .await?;
+
+        match response.status {
+            ShciStatus::Success => Ok(response),
+            status => Err(BleError::ShciError(status)),
+        }
+    }
+
+    /// returns the state of the FUS.
+    ///
+    /// When the wireless firmware is running, it answers with
+    /// [`ShciStatus::FusCmdNotSupported`] and restarts CPU2 into the FUS on the second call.
+    pub async fn get_state(&mut self) -> Result<FusStatus, BleError<BleTransportLayerError>> {
+        let response = self
+            .ble
+            .perform_shci_command(ShciOpcode::FusGetState as u16, &[])
+            .await?;
+
+        // the state is returned in place of the status, 0xff either means that the FUS is in
+        // the error state or that the wireless firmware does not support the command
+        if response.status == ShciStatus::FusCmdNotSupported
+            && self.ble.firmware() == FirmwareKind::Wireless
+        {
+            return Err(BleError::ShciError(response.status));
+        }
+
+        Ok(FusStatus {
+            state: FusState::from(u8::from(response.status)),
+            error: FusError::from(response.payload().first().copied().unwrap_or(0)),
+        })
+    }
+
+    /// installs the wireless or FUS binary previously written to flash.
+    ///
+    /// The FUS looks for the binary itself when no addresses are given, a destination address
+    /// needs a source address. CPU2 restarts while the binary is installed, see
+    /// [`Ble::wait_coprocessor_ready`].
+    pub async fn fw_upgrade(
+        &mut self,
+        src_address: Option<u32>,
+        dst_address: Option<u32>,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        let mut payload = [0u8; 8];
+        let len = match (src_address, dst_address) {
+            (None, None) => 0,
+            (None, Some(_)) => return Err(BleError::InvalidArgument),
+            (Some(src), None) => {
+                payload[..4].copy_from_slice(&src.to_le_bytes());
+                4
+            }
+            (Some(src), Some(dst)) => {
+                payload[..4].copy_from_slice(&src.to_le_bytes());
+                payload[4..].copy_from_slice(&dst.to_le_bytes());
+                8
+            }
+        };
+
+        self.command(ShciOpcode::FusFwUpgrade, &payload[..len])
+            .await
+            .map(|_| ())
+    }
+
+    /// deletes the wireless firmware
+    pub async fn fw_delete(&mut self) -> Result<(), BleError<BleTransportLayerError>> {
+        self.command(ShciOpcode::FusFwDelete, &[]).await.map(|_| ())
+    }
+
+    /// replaces the key used to authenticate the wireless binaries, at most
+    /// [`FUS_AUTH_KEY_MAX_SIZE`] bytes
+    pub async fn update_auth_key(
+        &mut self,
+        key: &[u8],
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        if key.len() > FUS_AUTH_KEY_MAX_SIZE {
+            return Err(BleError::PayloadTooLong(key.len()));
+        }
+
+        let mut payload = [0u8; FUS_AUTH_KEY_MAX_SIZE + 1];
+        payload[0] = key.len() as u8;
+        payload[1..=key.len()].copy_from_slice(key);
+
+        self.command(ShciOpcode::FusUpdateAuthKey, &payload[..=key.len()])
+            .await
+            .map(|_| ())
+    }
+
+    /// prevents any further update of the authentication key
+    pub async fn lock_auth_key(&mut self) -> Result<(), BleError<BleTransportLayerError>> {
+        self.command(ShciOpcode::FusLockAuthKey, &[])
+            .await
+            .map(|_| ())
+    }
+
+    /// stores a key of at most [`FUS_USR_KEY_MAX_SIZE`] bytes in CPU2's secure flash and returns
+    /// its index
+    pub async fn store_usr_key(
+        &mut self,
+        key_type: FusKeyType,
+        key: &[u8],
+    ) -> Result<u8, BleError<BleTransportLayerError>> {
+        if key.len() > FUS_USR_KEY_MAX_SIZE {
+            return Err(BleError::PayloadTooLong(key.len()));
+        }
+
+        let mut payload = [0u8; FUS_USR_KEY_MAX_SIZE + 2];
+        payload[0] = key_type as u8;
+        payload[1] = key.len() as u8;
+        payload[2..key.len() + 2].copy_from_slice(key);
+
+        let response = self
+            .command(ShciOpcode::FusStoreUsrKey, &payload[..key.len() + 2])
+            .await?;
+
+        response
+            .payload()
+            .first()
+            .copied()
+            .ok_or(BleError::UnexpectedEvent)
+    }
+
+    /// loads a stored key into the AES1 peripheral
+    pub async fn load_usr_key(
+        &mut self,
+        key_index: u8,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.command(ShciOpcode::FusLoadUsrKey, &[key_index])
+            .await
+            .map(|_| ())
+    }
+
+    /// starts the wireless firmware, CPU2 restarts, see [`Ble::wait_coprocessor_ready`]
+    pub async fn start_ws(&mut self) -> Result<(), BleError<BleTransportLayerError>> {
+        self.command(ShciOpcode::FusStartWs, &[]).await.map(|_| ())
+    }
+
+    /// prevents the installation of a wireless firmware older than the current one
+    pub async fn activate_antirollback(&mut self) -> Result<(), BleError<BleTransportLayerError>> {
+        self.command(ShciOpcode::FusActivateAntirollback, &[])
+            .await
+            .map(|_| ())
+    }
+}
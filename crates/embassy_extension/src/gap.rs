@@ -0,0 +1,148 @@
+//! Typed GAP commands of the BLE stack, see [`Ble::gap`].
+//!
+//! ```ignore
+//! let gap = ble.gap().init(Role::PERIPHERAL, false, DEVICE_NAME.len() as u8).await?;
+//! ble.gap().set_io_capability(IoCapability::DisplayConfirm).await?;
+//! ble.gap().set_discoverable(&discoverable_params).await?;
+//! ```
+
+use bluetooth_hci::ConnectionHandle;
+
+use crate::{
+    ble::{Ble, BleError, BleTransportLayerError, Completion},
+    hci::{
+        command::gap::{
+            AuthenticationRequirements, DiscoverableParameters, GapCommands, IoCapability, Role,
+        },
+        event::{
+            command::{GapInit, ReturnParameters},
+            Status,
+        },
+    },
+};
+
+/// handle for sending GAP commands, see [`Ble::gap`]
+pub struct Gap<'a> {
+    ble: &'a mut Ble,
+}
+
+impl<'a> Gap<'a> {
+    pub(crate) fn new(ble: &'a mut Ble) -> Self {
+        Self { ble }
+    }
+
+    /// initializes the GAP layer and returns the handles of the GAP service it adds.
+    ///
+    /// The GATT server is initialized first, see [`Gatt::init`](crate::gatt::Gatt::init).
+    pub async fn init(
+        &mut self,
+        role: Role,
+        privacy_enabled: bool,
+        dev_name_characteristic_len: u8,
+    ) -> Result<GapInit, BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.init_gap(role, privacy_enabled, dev_name_characteristic_len),
+                |return_params| match return_params {
+                    ReturnParameters::GapInit(gap) => Some((gap.status, gap)),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// sets the IO capability used for pairing
+    pub async fn set_io_capability(
+        &mut self,
+        capability: IoCapability,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.set_io_capability(capability),
+                |return_params| match return_params {
+                    ReturnParameters::GapSetIoCapability(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// sets the authentication requirements, only while not connected
+    pub async fn set_authentication_requirement(
+        &mut self,
+        requirements: &AuthenticationRequirements,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| {
+                    rc.set_authentication_requirement(requirements)
+                        .map_err(|e| e.map(|_| ()))
+                },
+                |return_params| match return_params {
+                    ReturnParameters::GapSetAuthenticationRequirement(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// starts advertising in general discoverable mode
+    pub async fn set_discoverable(
+        &mut self,
+        params: &DiscoverableParameters<'_, '_>,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.set_discoverable(params).map_err(|e| e.map(|_| ())),
+                |return_params| match return_params {
+                    ReturnParameters::GapSetDiscoverable(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// stops advertising
+    pub async fn set_nondiscoverable(&mut self) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.set_nondiscoverable(),
+                |return_params| match return_params {
+                    ReturnParameters::GapSetNonDiscoverable(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// updates the advertising data while advertising
+    pub async fn update_advertising_data(
+        &mut self,
+        data: &[u8],
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.update_advertising_data(data).map_err(|e| e.map(|_| ())),
+                |return_params| match return_params {
+                    ReturnParameters::GapUpdateAdvertisingData(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// terminates a connection and awaits its disconnection complete event
+    pub async fn terminate(
+        &mut self,
+        conn_handle: ConnectionHandle,
+        reason: bluetooth_hci::Status<Status>,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_procedure(
+                |rc| rc.terminate(conn_handle, reason).map_err(|e| e.map(|_| ())),
+                Completion::Disconnection(conn_handle),
+            )
+            .await
+            .map(|_| ())
+    }
+}
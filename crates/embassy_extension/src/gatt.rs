@@ -0,0 +1,221 @@
+//! Typed GATT commands of the BLE stack, see [`Ble::gatt`].
+//!
+//! ```ignore
+//! ble.gatt().init().await?;
+//!
+//! let service = ble.gatt().add_service(&service_params).await?;
+//! let characteristic = ble.gatt().add_characteristic(&characteristic_params).await?;
+//! ```
+
+use bluetooth_hci::ConnectionHandle;
+
+use crate::{
+    ble::{Ble, BleError, BleTransportLayerError},
+    hci::{
+        command::gatt::{
+            AddCharacteristicParameters, AddDescriptorParameters, AddServiceParameters,
+            CharacteristicHandle, DescriptorValueParameters, GattCommands, ServiceHandle,
+            UpdateCharacteristicValueParameters, WriteResponseParameters,
+        },
+        event::command::{
+            GattCharacteristic, GattCharacteristicDescriptor, GattHandleValue, GattService,
+            ReturnParameters,
+        },
+    },
+};
+
+/// handle for sending GATT commands, see [`Ble::gatt`]
+pub struct Gatt<'a> {
+    ble: &'a mut Ble,
+}
+
+impl<'a> Gatt<'a> {
+    pub(crate) fn new(ble: &'a mut Ble) -> Self {
+        Self { ble }
+    }
+
+    /// initializes the GATT server, before [`Gap::init`](crate::gap::Gap::init)
+    pub async fn init(&mut self) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.init_gatt(),
+                |return_params| match return_params {
+                    ReturnParameters::GattInit(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// adds a service and returns its handle
+    pub async fn add_service(
+        &mut self,
+        params: &AddServiceParameters,
+    ) -> Result<GattService, BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.add_service(params),
+                |return_params| match return_params {
+                    ReturnParameters::GattAddService(service) => Some((service.status, service)),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// deletes a service and its characteristics
+    pub async fn delete_service(
+        &mut self,
+        service: ServiceHandle,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.delete_service(service),
+                |return_params| match return_params {
+                    ReturnParameters::GattDeleteService(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// adds a characteristic to a service and returns its handle
+    pub async fn add_characteristic(
+        &mut self,
+        params: &AddCharacteristicParameters,
+    ) -> Result<GattCharacteristic, BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.add_characteristic(params),
+                |return_params| match return_params {
+                    ReturnParameters::GattAddCharacteristic(characteristic) => {
+                        Some((characteristic.status, characteristic))
+                    }
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// adds a descriptor to a characteristic and returns its handle
+    pub async fn add_characteristic_descriptor(
+        &mut self,
+        params: &AddDescriptorParameters<'_>,
+    ) -> Result<GattCharacteristicDescriptor, BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| {
+                    rc.add_characteristic_descriptor(params)
+                        .map_err(|e| e.map(|_| ()))
+                },
+                |return_params| match return_params {
+                    ReturnParameters::GattAddCharacteristicDescriptor(descriptor) => {
+                        Some((descriptor.status, descriptor))
+                    }
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// deletes a characteristic of a service
+    pub async fn delete_characteristic(
+        &mut self,
+        service: ServiceHandle,
+        characteristic: CharacteristicHandle,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.delete_characteristic(service, characteristic),
+                |return_params| match return_params {
+                    ReturnParameters::GattDeleteCharacteristic(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// updates the value of a characteristic, notifying or indicating the clients that enabled
+    /// it
+    pub async fn update_characteristic_value(
+        &mut self,
+        params: &UpdateCharacteristicValueParameters<'_>,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| {
+                    rc.update_characteristic_value(params)
+                        .map_err(|e| e.map(|_| ()))
+                },
+                |return_params| match return_params {
+                    ReturnParameters::GattUpdateCharacteristicValue(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// sets the value of a descriptor
+    pub async fn set_descriptor_value(
+        &mut self,
+        params: &DescriptorValueParameters<'_>,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.set_descriptor_value(params).map_err(|e| e.map(|_| ())),
+                |return_params| match return_params {
+                    ReturnParameters::GattSetDescriptorValue(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// reads the value of an attribute of the local GATT database
+    pub async fn read_handle_value(
+        &mut self,
+        handle: CharacteristicHandle,
+    ) -> Result<GattHandleValue, BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.read_handle_value(handle),
+                |return_params| match return_params {
+                    ReturnParameters::GattReadHandleValue(value) => Some((value.status, value)),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// answers a write request or a prepare write request of a client
+    pub async fn write_response(
+        &mut self,
+        params: &WriteResponseParameters<'_>,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.write_response(params).map_err(|e| e.map(|_| ())),
+                |return_params| match return_params {
+                    ReturnParameters::GattWriteResponse(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// lets the stack answer a pending read request of a client
+    pub async fn allow_read(
+        &mut self,
+        conn_handle: ConnectionHandle,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.allow_read(conn_handle),
+                |return_params| match return_params {
+                    ReturnParameters::GattAllowRead(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+}
@@ -0,0 +1,102 @@
+//! Typed HAL commands of the BLE stack, see [`Ble::hal`].
+//!
+//! ```ignore
+//! let revision = ble.hal().get_firmware_revision().await?;
+//! ble.hal().write_config_data(&ConfigData::public_address(addr).build()).await?;
+//! ```
+
+use crate::{
+    ble::{Ble, BleError, BleTransportLayerError},
+    hci::{
+        command::hal::{ConfigData, ConfigParameter, HalCommands, PowerLevel, RadioActivityFlags},
+        event::command::{HalConfigParameter, ReturnParameters},
+    },
+};
+
+/// handle for sending HAL commands, see [`Ble::hal`]
+pub struct Hal<'a> {
+    ble: &'a mut Ble,
+}
+
+impl<'a> Hal<'a> {
+    pub(crate) fn new(ble: &'a mut Ble) -> Self {
+        Self { ble }
+    }
+
+    /// returns the firmware revision of the BLE stack
+    pub async fn get_firmware_revision(&mut self) -> Result<u16, BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.get_firmware_revision(),
+                |return_params| match return_params {
+                    ReturnParameters::HalGetFirmwareRevision(fw) => Some((fw.status, fw.revision)),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// writes a value of the low level configuration, e.g. the public address or the root keys
+    pub async fn write_config_data(
+        &mut self,
+        config: &ConfigData,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.write_config_data(config),
+                |return_params| match return_params {
+                    ReturnParameters::HalWriteConfigData(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// reads a value of the low level configuration
+    pub async fn read_config_data(
+        &mut self,
+        param: ConfigParameter,
+    ) -> Result<HalConfigParameter, BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.read_config_data(param),
+                |return_params| match return_params {
+                    ReturnParameters::HalReadConfigData(data) => Some((data.status, data.value)),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// sets the TX power level
+    pub async fn set_tx_power_level(
+        &mut self,
+        level: PowerLevel,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.set_tx_power_level(level),
+                |return_params| match return_params {
+                    ReturnParameters::HalSetTxPowerLevel(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+
+    /// selects the radio activities whose end CPU2 reports with an end of radio activity event
+    pub async fn set_radio_activity_mask(
+        &mut self,
+        mask: RadioActivityFlags,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.ble
+            .perform_vendor_command(
+                |rc| rc.set_radio_activity_mask(mask),
+                |return_params| match return_params {
+                    ReturnParameters::HalSetRadioActivityMask(status) => Some((status, ())),
+                    _ => None,
+                },
+            )
+            .await
+    }
+}
@@ -13,6 +13,16 @@
 //!
 //! STM32WB55 BLE stack implements 4.x and 5.x versions of the Bluetooth [specification].
 //!
+//! # Command Flow Control
+//!
+//! BLE commands share a single buffer with CPU2. A command written while the controller has not
+//! granted a credit, with the Num_HCI_Command_Packets of a command complete or command status
+//! event, is held by [`RadioCoprocessor`] and sent by [`RadioCoprocessor::process_events`] once the
+//! credit arrives. Writing fails with [`nb::Error::WouldBlock`] when the held commands fill the
+//! queue.
+//!
+//! The responses are matched against the opcode of the command in flight, see [`flow`].
+//!
 //!
 //! # Vendor-Specific Commands
 //!
@@ -36,45 +46,74 @@ use bluetooth_hci::{
 pub use bluetooth_hci::host::{AdvertisingFilterPolicy, AdvertisingType, OwnAddressType};
 
 use crate::{
+    hci::{
+        acl::{AclData, AclError},
+        flow::{CommandDiagnostic, CommandFlow},
+    },
     ipcc::Ipcc,
-    tl_mbox::{self, cmd::CmdSerial, consts::TlPacketType, shci::ShciBleInitCmdParam, TlMbox},
+    shci::event::SysEvent,
+    tl_mbox::{
+        self, channels, cmd::CmdSerial, consts::TlPacketType, shci::ShciResponse,
+        snapshot::MboxSnapshot, stats::MboxStats, TlMbox,
+    },
 };
 
+pub mod acl;
 pub mod command;
 pub mod event;
+pub mod flow;
 pub mod opcode;
 
 const TX_BUF_SIZE: usize = core::mem::size_of::<CmdSerial>();
+const CMD_QUEUE_SIZE: usize = 4;
+const ACL_RX_QUEUE_SIZE: usize = 4;
+const SYS_EVT_BUF_SIZE: usize = 3 + 255;
+const SYS_EVT_QUEUE_SIZE: usize = 8;
 
 /// handle for interfacing with the STM32WB5x radio coprocessor
 pub struct RadioCoprocessor<'buf, const N: usize> {
     mbox: TlMbox,
     ipcc: Ipcc<'buf>,
-    config: ShciBleInitCmdParam,
     buff_producer: Producer<'buf, N>,
     buff_consumer: Consumer<'buf, N>,
     tx_buf: [u8; TX_BUF_SIZE],
-    is_ble_ready: bool,
+    /// bytes written into the HCI buffer and not read yet
+    hci_buffered: usize,
+    hci_buffer_high_water: usize,
+    sys_events: heapless::spsc::Queue<SysEvent, SYS_EVT_QUEUE_SIZE>,
+    acl_rx: heapless::spsc::Queue<AclData, ACL_RX_QUEUE_SIZE>,
+    /// opcode of the system command written with [`Controller::write`], its response is read
+    /// with [`RadioCoprocessor::read_sys_response`]
+    sys_cmd_in_stream: Option<u16>,
+    cmd_flow: CommandFlow,
+    cmd_queue: heapless::spsc::Queue<heapless::Vec<u8, TX_BUF_SIZE>, CMD_QUEUE_SIZE>,
 }
 
 impl<'buf, const N: usize> RadioCoprocessor<'buf, N> {
     /// creates a new [`RadioCoprocessor`] instance to send commands and to
-    /// receive events from
+    /// receive events from.
+    ///
+    /// No wireless stack is started: once CPU2 reported it is ready, the stack is started with
+    /// its SHCI command, e.g. the BLE init command.
     pub fn new(
         producer: Producer<'buf, N>,
         consumer: Consumer<'buf, N>,
         mbox: TlMbox,
         ipcc: Ipcc<'buf>,
-        config: ShciBleInitCmdParam,
     ) -> Self {
         Self {
             mbox,
             ipcc,
-            config,
             buff_producer: producer,
             buff_consumer: consumer,
             tx_buf: [0u8; TX_BUF_SIZE],
-            is_ble_ready: false,
+            hci_buffered: 0,
+            hci_buffer_high_water: 0,
+            sys_events: heapless::spsc::Queue::new(),
+            acl_rx: heapless::spsc::Queue::new(),
+            sys_cmd_in_stream: None,
+            cmd_flow: CommandFlow::new(),
+            cmd_queue: heapless::spsc::Queue::new(),
         }
     }
 
@@ -96,14 +135,87 @@ impl<'buf, const N: usize> RadioCoprocessor<'buf, N> {
         self.mbox.interrupt_ipcc_tx_handler(&mut self.ipcc);
     }
 
+    /// registers the Thread buffers in the mailbox, see [`TlMbox::init_thread`]
+    pub fn init_thread(&mut self) {
+        self.mbox.init_thread(&mut self.ipcc);
+    }
+
+    /// sends an SHCI command to CPU2, the response can be retrieved with
+    /// [`RadioCoprocessor::take_shci_response`] once the IPCC TX interrupt fired.
+    ///
+    /// Fails if the payload is longer than 255 bytes.
+    pub fn write_shci_command(&mut self, opcode: u16, payload: &[u8]) -> Result<(), ()> {
+        self.sys_cmd_in_stream = None;
+        tl_mbox::shci::shci_send_cmd(&mut self.ipcc, opcode, payload)
+    }
+
+    /// returns the response to the last SHCI command, if it was received
+    pub fn take_shci_response(&mut self) -> Option<ShciResponse> {
+        self.mbox.pop_sys_rsp()
+    }
+
+    /// takes a snapshot of the mailbox shared memory, see [`TlMbox::snapshot`]
+    pub fn mbox_snapshot(&self) -> MboxSnapshot {
+        self.mbox.snapshot(&self.ipcc)
+    }
+
+    /// returns a snapshot of the mailbox counters, see [`TlMbox::stats`]
+    pub fn mbox_stats(&self) -> MboxStats {
+        self.mbox.stats()
+    }
+
+    /// resets the mailbox counters and the HCI buffer high-water mark
+    pub fn reset_stats(&mut self) {
+        self.mbox.reset_stats();
+        self.reset_hci_buffer_high_water();
+    }
+
+    /// returns the largest number of bytes that were waiting in the HCI buffer
+    pub fn hci_buffer_high_water(&self) -> usize {
+        self.hci_buffer_high_water
+    }
+
+    /// resets the HCI buffer high-water mark
+    pub fn reset_hci_buffer_high_water(&mut self) {
+        self.hci_buffer_high_water = self.hci_buffered;
+    }
+
     /// call this function outside of interrupt context, for example in `main()` loop.
     /// Returns `true` if events were written and can be read with HCI `read()` function.
     /// returns `false` if no HCI events were written
+    ///
+    /// Sends the commands held until the controller granted a credit.
+    ///
+    /// System events are not HCI events, they are read with [`RadioCoprocessor::read_sys_event`].
+    ///
+    /// Responses to SHCI commands sent with [`RadioCoprocessor::write_shci_command`] are not HCI
+    /// events, they are kept until they are taken with [`RadioCoprocessor::take_shci_response`].
+    /// Responses to system commands written with [`Controller::write`] are read with
+    /// [`RadioCoprocessor::read_sys_response`].
     pub fn process_events(&mut self) -> bool {
+        let mut written = false;
+
         while let Some(evt) = self.mbox.dequeue_event() {
             defmt::debug!("processing event");
 
-            let event = evt.evt();
+            // ACL data is not an HCI event, it is kept until it is read with `read_acl_data`
+            if evt.evt().kind() == TlPacketType::AclData as u8 {
+                let mut buf = [0u8; acl::ACL_HEADER_SIZE + acl::ACL_DATA_MAX_LEN];
+                let packet = evt
+                    .write(&mut buf)
+                    .map_err(|_| AclError::Truncated)
+                    .and_then(|len| AclData::parse(&buf[..len]));
+
+                match packet {
+                    Ok(packet) => {
+                        if self.acl_rx.enqueue(packet).is_err() {
+                            defmt::warn!("ACL data queue is full, dropping packet");
+                        }
+                    }
+                    Err(e) => defmt::warn!("dropping ACL data: {}", e),
+                }
+                continue;
+            }
 
             let mut buf = self
                 .buff_producer
@@ -112,22 +224,132 @@ impl<'buf, const N: usize> RadioCoprocessor<'buf, N> {
 
             evt.write(buf.buf()).expect("EVT_BUF_SIZE is too small");
 
-            if event.kind() == 18 {
-                defmt::debug!("processing event `coprocessor ready` detected");
-                tl_mbox::shci::shci_ble_init(&mut self.ipcc, self.config);
-                self.is_ble_ready = true;
-                buf.buf()[0] = 0x04; // replace event code with one that is supported by HCI
+            if !self.cmd_flow.receive(buf.buf()) {
+                // the response is reported by `read_diagnostic` instead
+                buf.commit(0);
+                continue;
             }
 
             buf.commit(evt.size().unwrap());
+            written = true;
+
+            self.hci_buffered += evt.size().unwrap();
+            self.hci_buffer_high_water = self.hci_buffer_high_water.max(self.hci_buffered);
+        }
+
+        while let Some(evt) = self.mbox.dequeue_sys_event() {
+            let mut buf = [0u8; SYS_EVT_BUF_SIZE];
+            let len = evt.write(&mut buf).expect("SYS_EVT_BUF_SIZE is too small");
+
+            // skip the packet type, the event code and the payload length
+            let event = match SysEvent::new(&buf[3..len]) {
+                Ok(event) => event,
+                Err(e) => {
+                    defmt::warn!("dropping system event: {}", e);
+                    continue;
+                }
+            };
+
+            if let SysEvent::C2Ready(_) = event {
+                // CPU2 restarted, the commands held for the previous stack are stale
+                self.cmd_flow.reset();
+                self.clear_queued_commands();
+            }
+
+            if self.sys_events.enqueue(event).is_err() {
+                defmt::warn!("system event queue is full, dropping {}", event);
+            }
+        }
+
+        self.send_queued_commands();
+
+        written
+    }
+
+    /// sends the held commands the controller has credits for
+    fn send_queued_commands(&mut self) {
+        while self.cmd_flow.can_send() {
+            match self.cmd_queue.dequeue() {
+                Some(command) => {
+                    let opcode = u16::from_le_bytes([command[1], command[2]]);
+                    self.cmd_flow.sent(opcode);
+                    tl_mbox::ble::ble_send_cmd(&mut self.ipcc, &command);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// returns the number of commands held until the controller grants a credit
+    pub fn queued_commands(&self) -> usize {
+        self.cmd_queue.len()
+    }
+
+    /// drops the commands held until the controller grants a credit
+    pub fn clear_queued_commands(&mut self) {
+        while self.cmd_queue.dequeue().is_some() {}
+    }
+
+    /// returns the opcode of the command sent to CPU2 and not answered yet
+    pub fn command_in_flight(&self) -> Option<u16> {
+        self.cmd_flow.in_flight()
+    }
+
+    /// stops awaiting the response to the command in flight, e.g. after a timeout. The late
+    /// response is reported as a [`CommandDiagnostic`] instead of being written into the HCI
+    /// buffer.
+    ///
+    /// CPU2 owns the command buffer until it answered, the next command is held until then or
+    /// until the stack was restarted.
+    pub fn abandon_command(&mut self) {
+        self.cmd_flow.abandon();
+    }
+
+    /// returns the next response that did not answer the command in flight, call
+    /// [`RadioCoprocessor::process_events`] beforehand
+    pub fn read_diagnostic(&mut self) -> Option<CommandDiagnostic> {
+        self.cmd_flow.read_diagnostic()
+    }
+
+    /// writes the response to the system command written with [`Controller::write`] into `buf`,
+    /// as a [`TlPacketType::SysRsp`] packet, and returns its length.
+    ///
+    /// The response is not written into the HCI buffer, which only holds HCI events.
+    pub fn read_sys_response(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let awaited = self.sys_cmd_in_stream?;
+        let response = self.mbox.pop_sys_rsp()?;
+        self.sys_cmd_in_stream = None;
+
+        if response.opcode != awaited {
+            defmt::warn!("response to SHCI command {:#06x} was not expected", response.opcode);
         }
 
-        if self.mbox.pop_last_cc_evt().is_some() {
-            defmt::debug!("processing events cc event detected");
-            return false;
+        match response.write(buf) {
+            Ok(len) => Some(len),
+            Err(()) => {
+                defmt::warn!("dropping response to SHCI command {:#06x}", response.opcode);
+                None
+            }
         }
+    }
 
-        true
+    /// returns the next system event, call [`RadioCoprocessor::process_events`] beforehand
+    pub fn read_sys_event(&mut self) -> Option<SysEvent> {
+        self.sys_events.dequeue()
+    }
+
+    /// returns the next ACL data packet received from CPU2, call
+    /// [`RadioCoprocessor::process_events`] beforehand
+    pub fn read_acl_data(&mut self) -> Option<AclData> {
+        self.acl_rx.dequeue()
+    }
+
+    /// sends an ACL data packet to CPU2.
+    ///
+    /// Returns [`nb::Error::WouldBlock`] until CPU2 released the buffer of the previous packet,
+    /// which raises the IPCC TX interrupt.
+    pub fn write_acl_data(&mut self, packet: &AclData) -> nb::Result<(), ()> {
+        self.write(&packet.header(), packet.data())
     }
 }
 
@@ -140,23 +362,70 @@ impl<'buf, const N: usize> bluetooth_hci::Controller for RadioCoprocessor<'buf,
         let cmd_code = header[0];
         let cmd = TlPacketType::try_from(cmd_code).map_err(|_| ())?;
 
+        // checked before copying, the packet has to fit the buffer CPU2 reads it from
+        let len = header.len() + payload.len();
+        let max_len = match &cmd {
+            TlPacketType::AclData => acl::ACL_HEADER_SIZE + acl::ACL_DATA_MAX_LEN,
+            _ => TX_BUF_SIZE,
+        };
+        if len > max_len {
+            return Err(nb::Error::Other(()));
+        }
+
         self.tx_buf = [0; TX_BUF_SIZE];
         self.tx_buf[..header.len()].copy_from_slice(header);
-        self.tx_buf[header.len()..(header.len() + payload.len())].copy_from_slice(payload);
+        self.tx_buf[header.len()..len].copy_from_slice(payload);
 
         match &cmd {
             TlPacketType::AclData => {
                 // Destination buffer: ble table, phci_acl_data_buffer, acldataserial field
-                todo!()
+                // the buffer is in use until CPU2 clears the flag of the channel
+                if self
+                    .ipcc
+                    .c1_is_active_flag(channels::cpu1::IPCC_HCI_ACL_DATA_CHANNEL)
+                {
+                    return Err(nb::Error::WouldBlock);
+                }
+
+                tl_mbox::ble::ble_send_acl_data(&mut self.ipcc, &self.tx_buf[..len]);
+            }
+
+            TlPacketType::LocCmd => {
+                // CPU2 does not know the local commands, `tl_mbox::lhci::process` answers them
+                defmt::warn!("local commands are not sent to CPU2");
+                return Err(nb::Error::Other(()));
             }
 
             TlPacketType::SysCmd => {
                 // Destination buffer: SYS table, pcmdbuffer, cmdserial field
-                todo!()
+                // the buffer is in use until CPU2 answered the previous command
+                if self
+                    .ipcc
+                    .c1_is_active_flag(channels::cpu1::IPCC_SYSTEM_CMD_RSP_CHANNEL)
+                {
+                    return Err(nb::Error::WouldBlock);
+                }
+
+                let opcode = u16::from_le_bytes([header[1], header[2]]);
+                tl_mbox::shci::shci_send_cmd(&mut self.ipcc, opcode, payload)
+                    .map_err(nb::Error::Other)?;
+
+                // the response is read back with `read_sys_response`
+                self.sys_cmd_in_stream = Some(opcode);
             }
 
             _ => {
-                tl_mbox::ble::ble_send_cmd(&mut self.ipcc, &self.tx_buf[..]);
+                if self.cmd_flow.can_send() && self.cmd_queue.is_empty() {
+                    let opcode = u16::from_le_bytes([header[1], header[2]]);
+                    self.cmd_flow.sent(opcode);
+                    tl_mbox::ble::ble_send_cmd(&mut self.ipcc, &self.tx_buf[..len]);
+                } else {
+                    // held until a command complete or command status event grants a credit
+                    let command = heapless::Vec::from_slice(&self.tx_buf[..len]).map_err(|_| ())?;
+                    self.cmd_queue
+                        .enqueue(command)
+                        .map_err(|_| nb::Error::WouldBlock)?;
+                }
             }
         }
 
@@ -169,6 +438,7 @@ impl<'buf, const N: usize> bluetooth_hci::Controller for RadioCoprocessor<'buf,
                 if buffer.len() <= grant.buf().len() {
                     buffer.copy_from_slice(&grant.buf()[..buffer.len()]);
                     grant.release(buffer.len());
+                    self.hci_buffered = self.hci_buffered.saturating_sub(buffer.len());
 
                     Ok(())
                 } else {
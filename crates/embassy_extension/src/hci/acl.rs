@@ -0,0 +1,165 @@
+//! HCI ACL data packets.
+//!
+//! In link-layer only mode, or with a host stack running on CPU1, L2CAP traffic is exchanged with
+//! the controller as ACL data packets instead of GATT commands and events. CPU2 reads the packets
+//! sent by CPU1 from a single buffer and releases it with a TX interrupt of the ACL data channel,
+//! so a single packet is in flight at a time. Received packets arrive on the BLE event channel
+//! and are kept apart from the HCI events, see
+//! [`RadioCoprocessor::read_acl_data`](super::RadioCoprocessor::read_acl_data).
+
+use bluetooth_hci::ConnectionHandle;
+
+use crate::tl_mbox::consts::TlPacketType;
+
+/// Largest ACL payload the mailbox buffer holds, in bytes.
+pub const ACL_DATA_MAX_LEN: usize = 251;
+
+/// Packet type, handle and data length.
+pub(crate) const ACL_HEADER_SIZE: usize = 5;
+
+/// Place of a packet in an L2CAP PDU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum PacketBoundary {
+    /// first fragment of a PDU, sent by the host
+    FirstNonFlushable,
+    /// next fragment of a PDU
+    Continuing,
+    /// first fragment of a PDU, received from the controller
+    FirstFlushable,
+}
+
+impl PacketBoundary {
+    fn bits(self) -> u16 {
+        match self {
+            PacketBoundary::FirstNonFlushable => 0b00,
+            PacketBoundary::Continuing => 0b01,
+            PacketBoundary::FirstFlushable => 0b10,
+        }
+    }
+}
+
+/// Errors of the ACL data packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum AclError {
+    /// The payload is larger than [`ACL_DATA_MAX_LEN`], or the packet is larger than the buffer
+    /// it is written to. Includes its length.
+    TooLong(usize),
+    /// The packet is shorter than its header or than its data length.
+    Truncated,
+    /// The packet is not an ACL data packet. Includes its packet type.
+    BadPacketType(u8),
+    /// The packet boundary flag is not used by BLE.
+    BadPacketBoundary(u8),
+}
+
+/// An ACL data packet, sent with [`Ble::send_acl_data`](crate::ble::Ble::send_acl_data) or
+/// received with [`Ble::receive_acl_data`](crate::ble::Ble::receive_acl_data).
+#[derive(Clone)]
+pub struct AclData {
+    handle: ConnectionHandle,
+    boundary: PacketBoundary,
+    len: usize,
+    data: [u8; ACL_DATA_MAX_LEN],
+}
+
+impl AclData {
+    /// creates a packet carrying `data` on the connection `handle`
+    pub fn new(
+        handle: ConnectionHandle,
+        boundary: PacketBoundary,
+        data: &[u8],
+    ) -> Result<Self, AclError> {
+        if data.len() > ACL_DATA_MAX_LEN {
+            return Err(AclError::TooLong(data.len()));
+        }
+
+        let mut packet = Self {
+            handle,
+            boundary,
+            len: data.len(),
+            data: [0; ACL_DATA_MAX_LEN],
+        };
+        packet.data[..data.len()].copy_from_slice(data);
+
+        Ok(packet)
+    }
+
+    /// parses a packet starting with its packet type, as written by
+    /// [`EvtBox::write`](crate::tl_mbox::evt::EvtBox::write)
+    pub fn parse(packet: &[u8]) -> Result<Self, AclError> {
+        if packet.len() < ACL_HEADER_SIZE {
+            return Err(AclError::Truncated);
+        }
+        if packet[0] != TlPacketType::AclData as u8 {
+            return Err(AclError::BadPacketType(packet[0]));
+        }
+
+        let header = u16::from_le_bytes([packet[1], packet[2]]);
+        let boundary = match (header >> 12) & 0b11 {
+            0b00 => PacketBoundary::FirstNonFlushable,
+            0b01 => PacketBoundary::Continuing,
+            0b10 => PacketBoundary::FirstFlushable,
+            bits => return Err(AclError::BadPacketBoundary(bits as u8)),
+        };
+
+        let len = u16::from_le_bytes([packet[3], packet[4]]) as usize;
+        let data = packet
+            .get(ACL_HEADER_SIZE..ACL_HEADER_SIZE + len)
+            .ok_or(AclError::Truncated)?;
+
+        Self::new(ConnectionHandle(header & 0x0fff), boundary, data)
+    }
+
+    /// returns the connection the packet belongs to
+    pub fn handle(&self) -> ConnectionHandle {
+        self.handle
+    }
+
+    /// returns the place of the packet in its L2CAP PDU
+    pub fn boundary(&self) -> PacketBoundary {
+        self.boundary
+    }
+
+    /// returns the payload of the packet
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// returns the packet type and the header, the payload is [`AclData::data`]
+    pub(crate) fn header(&self) -> [u8; ACL_HEADER_SIZE] {
+        let handle = (self.handle.0 & 0x0fff) | self.boundary.bits() << 12;
+        let [handle_lo, handle_hi] = handle.to_le_bytes();
+        let [len_lo, len_hi] = (self.len as u16).to_le_bytes();
+
+        [
+            TlPacketType::AclData as u8,
+            handle_lo,
+            handle_hi,
+            len_lo,
+            len_hi,
+        ]
+    }
+
+    /// writes the packet, starting with its packet type, into `buf` and returns its length
+    pub fn write(&self, buf: &mut [u8]) -> Result<usize, AclError> {
+        let len = ACL_HEADER_SIZE + self.len;
+        if buf.len() < len {
+            return Err(AclError::TooLong(len));
+        }
+
+        buf[..ACL_HEADER_SIZE].copy_from_slice(&self.header());
+        buf[ACL_HEADER_SIZE..len].copy_from_slice(self.data());
+
+        Ok(len)
+    }
+}
+
+impl core::fmt::Debug for AclData {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("AclData")
+            .field("handle", &self.handle.0)
+            .field("boundary", &self.boundary)
+            .field("data", &self.data())
+            .finish()
+    }
+}
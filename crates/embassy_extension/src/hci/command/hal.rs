@@ -161,6 +161,19 @@ pub trait HalCommands {
     /// complete](crate::event::command::ReturnParameters::HalGetLinkStatus) event.
     fn get_link_status(&mut self) -> nb::Result<(), Self::Error>;
 
+    /// This command selects the radio activities that generate an [end of radio
+    /// activity](crate::hci::event::Stm32Wb5xEvent::HalEndOfRadioActivity) event.
+    ///
+    /// # Errors
+    ///
+    /// Only underlying communication errors are reported.
+    ///
+    /// # Generated events
+    ///
+    /// The controller will generate a [command
+    /// complete](crate::event::command::ReturnParameters::HalSetRadioActivityMask) event.
+    fn set_radio_activity_mask(&mut self, mask: RadioActivityFlags) -> nb::Result<(), Self::Error>;
+
     /// This command is intended to retrieve information about the current Anchor Interval and
     /// allocable timing slots.
     ///
@@ -230,6 +243,13 @@ impl<'buf, const N: usize> HalCommands for crate::hci::RadioCoprocessor<'buf, N>
     fn get_anchor_period(&mut self) -> nb::Result<(), Self::Error> {
         self.write_command(crate::hci::opcode::HAL_GET_ANCHOR_PERIOD, &[])
     }
+
+    fn set_radio_activity_mask(&mut self, mask: RadioActivityFlags) -> nb::Result<(), Self::Error> {
+        let mut bytes = [0; 2];
+        LittleEndian::write_u16(&mut bytes, mask.bits());
+
+        self.write_command(crate::hci::opcode::HAL_SET_RADIO_ACTIVITY_MASK, &bytes)
+    }
 }
 
 /// Potential errors from parameter validation.
@@ -557,6 +577,7 @@ pub enum Role {
 /// Configuration parameters that are readable by the
 /// [`read_config_data`](Commands::read_config_data) command.
 #[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ConfigParameter {
     /// Bluetooth public address.
     PublicAddress = 0,
@@ -582,9 +603,32 @@ pub enum ConfigParameter {
     Role = 41,
 }
 
+bitflags! {
+    /// Radio activities for [HAL Set Radio Activity Mask](HalCommands::set_radio_activity_mask).
+    pub struct RadioActivityFlags: u16 {
+        /// Idle
+        const IDLE = 0x0001;
+        /// Advertising
+        const ADVERTISING = 0x0002;
+        /// Connection event as a peripheral
+        const PERIPHERAL_CONNECTION = 0x0004;
+        /// Scanning
+        const SCANNING = 0x0008;
+        /// Connection request
+        const CONNECTION_REQUEST = 0x0010;
+        /// Connection event as a central
+        const CENTRAL_CONNECTION = 0x0020;
+        /// TX test mode
+        const TX_TEST = 0x0040;
+        /// RX test mode
+        const RX_TEST = 0x0080;
+    }
+}
+
 /// Transmitter power levels available for the system.
 ///
 /// STM32WB5x uses single byte parameter for PA level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum PowerLevel {
     /// -40 dBm.
@@ -683,3 +727,89 @@ pub enum PowerLevel {
     /// 6 dBm.
     Plus6dBm = 0x1F,
 }
+
+impl PowerLevel {
+    /// Output power of the radio, in dBm.
+    pub fn dbm(&self) -> f32 {
+        match self {
+            PowerLevel::Minus40dBm => -40.0,
+            PowerLevel::Minus20_85dBm => -20.85,
+            PowerLevel::Minus19_75dBm => -19.75,
+            PowerLevel::Minus18_85dBm => -18.85,
+            PowerLevel::Minus17_6dBm => -17.6,
+            PowerLevel::Minus16_5dBm => -16.5,
+            PowerLevel::Minus15_25dBm => -15.25,
+            PowerLevel::Minus14_1dBm => -14.1,
+            PowerLevel::Minus13_15dBm => -13.15,
+            PowerLevel::Minus12_05dBm => -12.05,
+            PowerLevel::Minus10_9dBm => -10.9,
+            PowerLevel::Minus9_9dBm => -9.9,
+            PowerLevel::Minus8_85dBm => -8.85,
+            PowerLevel::Minus7_8dBm => -7.8,
+            PowerLevel::Minus6_9dBm => -6.9,
+            PowerLevel::Minus5_9dBm => -5.9,
+            PowerLevel::Minus4_95dBm => -4.95,
+            PowerLevel::Minus4dBm => -4.0,
+            PowerLevel::Minus3_15dBm => -3.15,
+            PowerLevel::Minus2_45dBm => -2.45,
+            PowerLevel::Minus1_8dBm => -1.8,
+            PowerLevel::Minus1_3dBm => -1.3,
+            PowerLevel::Minus0_85dBm => -0.85,
+            PowerLevel::Minus0_5dBm => -0.5,
+            PowerLevel::Minus0_15dBm => -0.15,
+            PowerLevel::ZerodBm => 0.0,
+            PowerLevel::Plus1dBm => 1.0,
+            PowerLevel::Plus2dBm => 2.0,
+            PowerLevel::Plus3dBm => 3.0,
+            PowerLevel::Plus4dBm => 4.0,
+            PowerLevel::Plus5dBm => 5.0,
+            PowerLevel::Plus6dBm => 6.0,
+        }
+    }
+
+    /// Highest power level whose output power does not exceed `dbm`, or the lowest level if
+    /// none does.
+    pub fn at_most(dbm: f32) -> PowerLevel {
+        PowerLevel::ALL
+            .iter()
+            .rev()
+            .find(|level| level.dbm() <= dbm)
+            .copied()
+            .unwrap_or(PowerLevel::Minus40dBm)
+    }
+
+    const ALL: [PowerLevel; 32] = [
+        PowerLevel::Minus40dBm,
+        PowerLevel::Minus20_85dBm,
+        PowerLevel::Minus19_75dBm,
+        PowerLevel::Minus18_85dBm,
+        PowerLevel::Minus17_6dBm,
+        PowerLevel::Minus16_5dBm,
+        PowerLevel::Minus15_25dBm,
+        PowerLevel::Minus14_1dBm,
+        PowerLevel::Minus13_15dBm,
+        PowerLevel::Minus12_05dBm,
+        PowerLevel::Minus10_9dBm,
+        PowerLevel::Minus9_9dBm,
+        PowerLevel::Minus8_85dBm,
+        PowerLevel::Minus7_8dBm,
+        PowerLevel::Minus6_9dBm,
+        PowerLevel::Minus5_9dBm,
+        PowerLevel::Minus4_95dBm,
+        PowerLevel::Minus4dBm,
+        PowerLevel::Minus3_15dBm,
+        PowerLevel::Minus2_45dBm,
+        PowerLevel::Minus1_8dBm,
+        PowerLevel::Minus1_3dBm,
+        PowerLevel::Minus0_85dBm,
+        PowerLevel::Minus0_5dBm,
+        PowerLevel::Minus0_15dBm,
+        PowerLevel::ZerodBm,
+        PowerLevel::Plus1dBm,
+        PowerLevel::Plus2dBm,
+        PowerLevel::Plus3dBm,
+        PowerLevel::Plus4dBm,
+        PowerLevel::Plus5dBm,
+        PowerLevel::Plus6dBm,
+    ];
+}
@@ -52,6 +52,10 @@ pub enum ReturnParameters {
     /// command.
     HalGetAnchorPeriod(HalAnchorPeriod),
 
+    /// Status returned by the [HAL Set Radio Activity
+    /// Mask](crate::hci::command::hal::HalCommands::set_radio_activity_mask) command.
+    HalSetRadioActivityMask(bluetooth_hci::Status<crate::hci::event::Status>),
+
     /// Status returned by the [GAP Set Non-Discoverable](crate::gap::Commands::set_nondiscoverable)
     /// command.
     GapSetNonDiscoverable(bluetooth_hci::Status<crate::hci::event::Status>),
@@ -277,6 +281,9 @@ impl bluetooth_hci::event::VendorReturnParameters for ReturnParameters {
             crate::hci::opcode::HAL_GET_ANCHOR_PERIOD => {
                 Ok(ReturnParameters::HalGetAnchorPeriod(to_hal_anchor_period(&bytes[3..])?))
             }
+            crate::hci::opcode::HAL_SET_RADIO_ACTIVITY_MASK => {
+                Ok(ReturnParameters::HalSetRadioActivityMask(to_status(&bytes[3..])?))
+            }
             crate::hci::opcode::GAP_SET_NONDISCOVERABLE => {
                 Ok(ReturnParameters::GapSetNonDiscoverable(to_status(&bytes[3..])?))
             }
@@ -647,8 +654,9 @@ pub enum LinkState {
     ConnectedAsPeripheral,
     /// Scanning
     Scanning,
-    /// Reserved
-    Reserved,
+    /// Initiating a connection, only reported by
+    /// [`HalEndOfRadioActivity`](super::Stm32Wb5xEvent::HalEndOfRadioActivity)
+    ConnectionRequest,
     /// Connected in primary role
     ConnectedAsPrimary,
     /// TX Test
@@ -666,7 +674,7 @@ impl TryFrom<u8> for LinkState {
             1 => Ok(LinkState::Advertising),
             2 => Ok(LinkState::ConnectedAsPeripheral),
             3 => Ok(LinkState::Scanning),
-            4 => Ok(LinkState::Reserved),
+            4 => Ok(LinkState::ConnectionRequest),
             5 => Ok(LinkState::ConnectedAsPrimary),
             6 => Ok(LinkState::TxTest),
             7 => Ok(LinkState::RxTest),
@@ -8,6 +8,7 @@ pub mod command;
 
 use bluetooth_hci::host::EventFlags;
 use byteorder::{ByteOrder, LittleEndian};
+use command::LinkState;
 use core::cmp::PartialEq;
 use core::convert::{TryFrom, TryInto};
 use core::fmt::{Debug, Formatter, Result as FmtResult};
@@ -23,6 +24,9 @@ pub use bluetooth_hci::{BdAddr, BdAddrType, ConnectionHandle};
 pub enum Stm32Wb5xEvent {
     /// When the radio coprocessor firmware is started normally, it gives this event to the user to
     /// indicate the system has started.
+    ///
+    /// [`RadioCoprocessor`](crate::hci::RadioCoprocessor) delivers system events as
+    /// [`SysEvent`](crate::shci::event::SysEvent)s, this event is only parsed from raw buffers.
     CoprocessorReady(FirmwareKind),
 
     /// If the host fails to read events from the controller quickly enough, the controller will
@@ -37,6 +41,12 @@ pub enum Stm32Wb5xEvent {
     //#[cfg(feature = "ms")]
     // CrashReport(FaultData),
 
+    /// This event is generated when the radio completes one of the activities selected with
+    /// [HAL Set Radio Activity Mask](crate::hci::command::hal::HalCommands::set_radio_activity_mask)
+    /// and gives the next radio activity, e.g. to drive an external front-end module, see
+    /// [`crate::fem`].
+    HalEndOfRadioActivity(HalEndOfRadioActivity),
+
     /// This event is generated by the controller when the limited discoverable mode ends due to
     /// timeout (180 seconds).
     GapLimitedDiscoverableTimeout,
@@ -571,6 +581,10 @@ impl bluetooth_hci::event::VendorEvent for Stm32Wb5xEvent {
             // SHCI "C2 Ready" event
             0x9200 => Ok(Stm32Wb5xEvent::CoprocessorReady(to_coprocessor_ready(buffer)?)),
 
+            0x0004 => {
+                Ok(Stm32Wb5xEvent::HalEndOfRadioActivity(to_hal_end_of_radio_activity(buffer)?))
+            }
+
             0x0400 => Ok(Stm32Wb5xEvent::GapLimitedDiscoverableTimeout),
             0x0401 => Ok(Stm32Wb5xEvent::GapPairingComplete(to_gap_pairing_complete(buffer)?)),
             0x0402 => Ok(Stm32Wb5xEvent::GapPassKeyRequest(to_conn_handle(buffer)?)),
@@ -685,13 +699,19 @@ impl bluetooth_hci::event::VendorEvent for Stm32Wb5xEvent {
 
 /// Potential firmware kinds for [`CoprocessorReady`](Stm32Wb5xEvent::CoprocessorReady)
 /// event.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
 pub enum FirmwareKind {
     /// Wireless firmware (BLE, Thread, etc.)
     Wireless,
 
-    /// RCC firmware.
-    Rcc,
+    /// Firmware Upgrade Service, CPU2 only accepts FUS commands, see [`crate::fus`].
+    Fus,
+
+    /// NVM backup service, CPU2 saves the NVM data of the wireless stack before it is updated.
+    NvmBackup,
+
+    /// NVM restore service, CPU2 restores the NVM data saved by the backup service.
+    NvmRestore,
 }
 
 impl TryFrom<u8> for FirmwareKind {
@@ -700,7 +720,9 @@ impl TryFrom<u8> for FirmwareKind {
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
             0 => Ok(FirmwareKind::Wireless),
-            1 => Ok(FirmwareKind::Rcc),
+            1 => Ok(FirmwareKind::Fus),
+            0x10 => Ok(FirmwareKind::NvmBackup),
+            0x11 => Ok(FirmwareKind::NvmRestore),
             _ => Err(Stm32Wb5xError::UnknownFirmwareKind(value)),
         }
     }
@@ -722,6 +744,37 @@ fn to_coprocessor_ready(
         .map_err(bluetooth_hci::event::Error::Vendor)
 }
 
+/// This event is generated when the radio completes an activity, see
+/// [`HalEndOfRadioActivity`](Stm32Wb5xEvent::HalEndOfRadioActivity).
+#[derive(Copy, Clone, Debug)]
+pub struct HalEndOfRadioActivity {
+    /// Radio activity that just completed.
+    pub last_state: LinkState,
+
+    /// Next radio activity.
+    pub next_state: LinkState,
+
+    /// System time at which the next radio activity starts, in units of 625/256 µs.
+    pub next_state_sys_time: u32,
+}
+
+fn to_hal_end_of_radio_activity(
+    buffer: &[u8],
+) -> Result<HalEndOfRadioActivity, bluetooth_hci::event::Error<Stm32Wb5xError>> {
+    // newer firmwares append the slot numbers of both activities
+    require_len_at_least!(buffer, 8);
+
+    Ok(HalEndOfRadioActivity {
+        last_state: buffer[2]
+            .try_into()
+            .map_err(bluetooth_hci::event::Error::Vendor)?,
+        next_state: buffer[3]
+            .try_into()
+            .map_err(bluetooth_hci::event::Error::Vendor)?,
+        next_state_sys_time: LittleEndian::read_u32(&buffer[4..]),
+    })
+}
+
 macro_rules! require_l2cap_event_data_len {
     ($left:expr, $right:expr) => {
         let actual = $left[4];
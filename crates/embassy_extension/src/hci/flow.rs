@@ -0,0 +1,253 @@
+//! Flow control and correlation of the BLE commands.
+//!
+//! CPU2 reads the commands from a single buffer and answers each of them with a command complete
+//! or a command status event, whose Num_HCI_Command_Packets grants the credit for the next one.
+//! [`CommandFlow`] keeps the opcode of the command in flight and checks the opcode of each
+//! response against it. A response to a command that is not awaited would be taken for the
+//! response of the next command, so it is kept out of the HCI buffer and reported as a
+//! [`CommandDiagnostic`] instead, see
+//! [`RadioCoprocessor::read_diagnostic`](super::RadioCoprocessor::read_diagnostic).
+//!
+//! CPU2 owns the command buffer until it answered the command in flight, even an abandoned one.
+//! The credits are only taken while no command is in flight, a response to another command never
+//! releases the buffer.
+
+use crate::tl_mbox::consts::{TlPacketType, TL_BLEEVT_CC_OPCODE, TL_BLEEVT_CS_OPCODE};
+
+/// Opcode of the command complete events that only grant credits.
+const NOP_OPCODE: u16 = 0x0000;
+
+const DIAGNOSTIC_QUEUE_SIZE: usize = 4;
+
+/// Responses of CPU2 that do not answer the command in flight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum CommandDiagnostic {
+    /// a response arrived while no command was in flight. Includes its opcode.
+    UnexpectedResponse(u16),
+    /// the response to a command that was abandoned, e.g. after a timeout. Includes its opcode.
+    StaleResponse(u16),
+    /// a response to another command than the one in flight
+    MismatchedResponse {
+        /// opcode of the command in flight
+        awaited: u16,
+        /// opcode of the response
+        received: u16,
+    },
+}
+
+/// State of the command flow of a [`RadioCoprocessor`](super::RadioCoprocessor).
+pub(crate) struct CommandFlow {
+    /// commands the controller accepts before it answers
+    credits: u8,
+    /// opcode of the command sent to CPU2 and not answered yet, CPU2 owns the command buffer
+    /// until it answers
+    in_flight: Option<u16>,
+    /// the response to the command in flight is no longer awaited
+    abandoned: bool,
+    diagnostics: heapless::spsc::Queue<CommandDiagnostic, DIAGNOSTIC_QUEUE_SIZE>,
+}
+
+impl CommandFlow {
+    pub(crate) const fn new() -> Self {
+        Self {
+            credits: 1,
+            in_flight: None,
+            abandoned: false,
+            diagnostics: heapless::spsc::Queue::new(),
+        }
+    }
+
+    /// returns `true` if a command can be sent to CPU2
+    pub(crate) fn can_send(&self) -> bool {
+        self.credits > 0 && self.in_flight.is_none()
+    }
+
+    /// takes a credit for the command `opcode`
+    pub(crate) fn sent(&mut self, opcode: u16) {
+        self.credits = self.credits.saturating_sub(1);
+        self.in_flight = Some(opcode);
+        self.abandoned = false;
+    }
+
+    /// returns the opcode of the command in flight
+    pub(crate) fn in_flight(&self) -> Option<u16> {
+        self.in_flight
+    }
+
+    /// drops the response to the command in flight once it arrives.
+    ///
+    /// CPU2 may still read the command buffer, so the next command is only sent once the late
+    /// response arrived, which is dropped as stale, or once the stack was restarted.
+    pub(crate) fn abandon(&mut self) {
+        if self.in_flight.is_some() {
+            self.abandoned = true;
+        }
+    }
+
+    /// forgets the commands of a stack that was restarted
+    pub(crate) fn reset(&mut self) {
+        self.credits = 1;
+        self.in_flight = None;
+        self.abandoned = false;
+    }
+
+    /// takes the credits of an event, starting with its packet type, and returns `false` if the
+    /// event is a response that does not answer the command in flight
+    pub(crate) fn receive(&mut self, packet: &[u8]) -> bool {
+        let (credits, opcode) = match command_response(packet) {
+            Some(response) => response,
+            None => return true,
+        };
+
+        if opcode == NOP_OPCODE {
+            if self.in_flight.is_none() {
+                self.grant(credits);
+            }
+            return true;
+        }
+
+        let diagnostic = match self.in_flight {
+            Some(awaited) if awaited == opcode => {
+                // CPU2 released the command buffer
+                self.in_flight = None;
+                self.grant(credits);
+
+                if !self.abandoned {
+                    return true;
+                }
+                CommandDiagnostic::StaleResponse(opcode)
+            }
+            // the command in flight still owns the buffer
+            Some(awaited) => CommandDiagnostic::MismatchedResponse {
+                awaited,
+                received: opcode,
+            },
+            None => {
+                self.grant(credits);
+                CommandDiagnostic::UnexpectedResponse(opcode)
+            }
+        };
+
+        defmt::warn!("dropping command response: {}", diagnostic);
+        if self.diagnostics.enqueue(diagnostic).is_err() {
+            defmt::warn!("command diagnostic queue is full");
+        }
+
+        false
+    }
+
+    fn grant(&mut self, credits: u8) {
+        // a single command buffer is shared with CPU2, so one command is in flight at most
+        self.credits = credits.min(1);
+    }
+
+    /// returns the next diagnostic
+    pub(crate) fn read_diagnostic(&mut self) -> Option<CommandDiagnostic> {
+        self.diagnostics.dequeue()
+    }
+}
+
+/// returns the Num_HCI_Command_Packets and the opcode of a command complete or command status
+/// event, starting with its packet type
+fn command_response(packet: &[u8]) -> Option<(u8, u16)> {
+    if packet.first() != Some(&(TlPacketType::BleEvt as u8)) {
+        return None;
+    }
+
+    let (credits, opcode) = match packet.get(1)? {
+        &TL_BLEEVT_CC_OPCODE => (packet.get(3)?, packet.get(4..6)?),
+        &TL_BLEEVT_CS_OPCODE => (packet.get(4)?, packet.get(5..7)?),
+        _ => return None,
+    };
+
+    Some((*credits, u16::from_le_bytes([opcode[0], opcode[1]])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// command complete event of `opcode`, granting `credits`
+    fn command_complete(credits: u8, opcode: u16) -> [u8; 7] {
+        let kind = TlPacketType::BleEvt as u8;
+        let [lo, hi] = opcode.to_le_bytes();
+        [kind, TL_BLEEVT_CC_OPCODE, 4, credits, lo, hi, 0x00]
+    }
+
+    #[test]
+    fn abandoned_command_keeps_the_buffer_until_answered() {
+        let mut flow = CommandFlow::new();
+
+        flow.sent(0x0c03);
+        assert!(!flow.can_send());
+
+        flow.abandon();
+        assert!(!flow.can_send());
+
+        assert!(!flow.receive(&command_complete(1, 0x0c03)));
+        assert!(flow.can_send());
+
+        flow.sent(0xfc0c);
+        assert_eq!(flow.in_flight(), Some(0xfc0c));
+        assert!(!flow.abandoned);
+    }
+
+    #[test]
+    fn stale_response_is_reported() {
+        let mut flow = CommandFlow::new();
+
+        flow.sent(0x0c03);
+        flow.abandon();
+
+        assert!(!flow.receive(&command_complete(1, 0x0c03)));
+        assert_eq!(flow.in_flight(), None);
+        assert_eq!(flow.read_diagnostic(), Some(CommandDiagnostic::StaleResponse(0x0c03)));
+    }
+
+    #[test]
+    fn mismatched_response_grants_no_credit() {
+        let mut flow = CommandFlow::new();
+
+        flow.sent(0xfc0c);
+
+        assert!(!flow.receive(&command_complete(1, 0x0c03)));
+        assert!(!flow.can_send());
+        assert_eq!(flow.in_flight(), Some(0xfc0c));
+        assert_eq!(
+            flow.read_diagnostic(),
+            Some(CommandDiagnostic::MismatchedResponse {
+                awaited: 0xfc0c,
+                received: 0x0c03,
+            })
+        );
+
+        assert!(flow.receive(&command_complete(1, 0xfc0c)));
+        assert!(flow.can_send());
+        assert_eq!(flow.read_diagnostic(), None);
+    }
+
+    #[test]
+    fn unexpected_response_is_reported() {
+        let mut flow = CommandFlow::new();
+
+        assert!(!flow.receive(&command_complete(1, 0x0c03)));
+        assert!(flow.can_send());
+        assert_eq!(flow.read_diagnostic(), Some(CommandDiagnostic::UnexpectedResponse(0x0c03)));
+    }
+
+    #[test]
+    fn nop_only_grants_credits_to_a_free_buffer() {
+        let mut flow = CommandFlow::new();
+
+        assert!(flow.receive(&command_complete(0, NOP_OPCODE)));
+        assert!(!flow.can_send());
+        assert!(flow.receive(&command_complete(1, NOP_OPCODE)));
+        assert!(flow.can_send());
+
+        flow.sent(0x0c03);
+        assert!(flow.receive(&command_complete(1, NOP_OPCODE)));
+        assert!(!flow.can_send());
+        assert_eq!(flow.in_flight(), Some(0x0c03));
+        assert_eq!(flow.read_diagnostic(), None);
+    }
+}
@@ -0,0 +1,207 @@
+//! Hardware semaphores shared with CPU2.
+//!
+//! ST's wireless firmware reserves some of the HSEM semaphores to arbitrate the peripherals and
+//! the flash shared by both cores: CPU1 has to hold the matching semaphore before it uses the RNG
+//! or the PKA, changes the clock configuration, enters a stop mode or operates on the flash.
+//!
+//! Semaphores are taken with either procedure of the reference manual:
+//!
+//! - the 1-step lock ([`try_lock`], [`lock`]) reads the semaphore and takes it with process ID 0,
+//! - the 2-step lock ([`try_lock_process`], [`lock_process`]) writes the semaphore with a process
+//!   ID and reads it back, so that several processes of CPU1 can share a semaphore.
+//!
+//! [`Hsem`] owns the HSEM interrupt of CPU1 to wait asynchronously until CPU2 releases a
+//! semaphore instead of spinning.
+
+use core::future::poll_fn;
+use core::task::Poll;
+
+use embassy_stm32::{
+    interrupt::{self, InterruptExt},
+    pac::HSEM,
+};
+use embassy_sync::waitqueue::AtomicWaker;
+
+/// Core ID of CPU1 in the HSEM registers.
+const COREID_CPU1: u8 = 0x4;
+
+/// Number of semaphores of the HSEM peripheral.
+const SEMAPHORE_COUNT: usize = 32;
+
+/// Semaphores reserved by ST's wireless firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum SemaphoreId {
+    /// RNG peripheral, used by CPU2 to seed the BLE stack
+    Rng = 0,
+    /// PKA peripheral
+    Pka = 1,
+    /// flash controller, taken by the core that erases or programs the flash
+    Flash = 2,
+    /// RCC registers shared by both cores
+    Rcc = 3,
+    /// entry into and exit from stop modes
+    StopMode = 4,
+    /// CLK48 configuration
+    Clk48 = 5,
+    /// taken by CPU1 to prevent CPU2 from accessing the flash
+    BlockFlashReqByCpu1 = 6,
+    /// held by CPU2 while it cannot be stalled by a flash operation of CPU1
+    BlockFlashReqByCpu2 = 7,
+}
+
+/// enables the HSEM clock
+pub fn enable() {
+    unsafe {
+        embassy_stm32::pac::RCC
+            .ahb3enr()
+            .modify(|w| w.set_hsemen(true))
+    }
+}
+
+/// tries to take the semaphore with the 1-step procedure, returns `true` if CPU1 now holds it
+pub fn try_lock(id: SemaphoreId) -> bool {
+    // reading RLR locks the semaphore if it is free
+    let rlr = unsafe { HSEM.rlr(id as usize).read() };
+
+    rlr.lock() && rlr.coreid() == COREID_CPU1 && rlr.procid() == 0
+}
+
+/// takes the semaphore with the 1-step procedure, spinning until the other core releases it
+pub fn lock(id: SemaphoreId) {
+    while !try_lock(id) {}
+}
+
+/// tries to take the semaphore with the 2-step procedure for process `process_id`, returns
+/// `true` if the process now holds it
+pub fn try_lock_process(id: SemaphoreId, process_id: u8) -> bool {
+    unsafe {
+        HSEM.r(id as usize).write(|w| {
+            w.set_coreid(COREID_CPU1);
+            w.set_procid(process_id);
+            w.set_lock(true);
+        });
+
+        let r = HSEM.r(id as usize).read();
+
+        r.lock() && r.coreid() == COREID_CPU1 && r.procid() == process_id
+    }
+}
+
+/// takes the semaphore with the 2-step procedure for process `process_id`, spinning until it is
+/// released
+pub fn lock_process(id: SemaphoreId, process_id: u8) {
+    while !try_lock_process(id, process_id) {}
+}
+
+/// releases the semaphore taken with the 1-step procedure
+pub fn unlock(id: SemaphoreId) {
+    unlock_process(id, 0)
+}
+
+/// releases the semaphore held by process `process_id`
+pub fn unlock_process(id: SemaphoreId, process_id: u8) {
+    unsafe {
+        HSEM.r(id as usize).write(|w| {
+            w.set_coreid(COREID_CPU1);
+            w.set_procid(process_id);
+            w.set_lock(false);
+        })
+    }
+}
+
+/// returns `true` if any core holds the semaphore
+pub fn is_locked(id: SemaphoreId) -> bool {
+    unsafe { HSEM.r(id as usize).read().lock() }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NEW_WAKER: AtomicWaker = AtomicWaker::new();
+static WAKERS: [AtomicWaker; SEMAPHORE_COUNT] = [NEW_WAKER; SEMAPHORE_COUNT];
+
+/// Waits for semaphores to be released using the HSEM interrupt of CPU1.
+pub struct Hsem {
+    irq: interrupt::HSEM,
+}
+
+impl Hsem {
+    /// enables the HSEM clock and registers the interrupt handler
+    pub fn new(irq: interrupt::HSEM) -> Self {
+        enable();
+
+        irq.disable();
+        irq.set_handler(Self::on_irq);
+        irq.set_handler_context(core::ptr::null_mut());
+        irq.enable();
+
+        Self { irq }
+    }
+
+    /// waits until no core holds the semaphore
+    pub async fn wait_released(&self, id: SemaphoreId) {
+        poll_fn(|cx| {
+            WAKERS[id as usize].register(cx.waker());
+
+            // the interrupt is raised when the semaphore is released after this point
+            set_interrupt(id, true);
+
+            if is_locked(id) {
+                Poll::Pending
+            } else {
+                set_interrupt(id, false);
+                Poll::Ready(())
+            }
+        })
+        .await
+    }
+
+    /// takes the semaphore with the 1-step procedure, waiting until the other core releases it
+    pub async fn lock(&self, id: SemaphoreId) {
+        while !try_lock(id) {
+            self.wait_released(id).await;
+        }
+    }
+
+    /// takes the semaphore with the 2-step procedure for process `process_id`, waiting until it
+    /// is released
+    pub async fn lock_process(&self, id: SemaphoreId, process_id: u8) {
+        while !try_lock_process(id, process_id) {
+            self.wait_released(id).await;
+        }
+    }
+
+    unsafe fn on_irq(_ctx: *mut ()) {
+        let misr = HSEM.misr(0).read();
+
+        for (index, waker) in WAKERS.iter().enumerate() {
+            if misr.isf(index) {
+                // a release interrupt is only needed once per wait
+                HSEM.ier(0).modify(|w| w.set_ise(index, false));
+                HSEM.icr(0).write(|w| w.set_isc(index, true));
+
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Drop for Hsem {
+    fn drop(&mut self) {
+        self.irq.disable();
+        self.irq.remove_handler();
+
+        unsafe { HSEM.ier(0).write(|w| w.0 = 0) }
+    }
+}
+
+/// enables or disables the release interrupt of the semaphore for CPU1
+fn set_interrupt(id: SemaphoreId, enabled: bool) {
+    cortex_m::interrupt::free(|_| unsafe {
+        if enabled {
+            // a stale release must not wake the waiting task at once
+            HSEM.icr(0).write(|w| w.set_isc(id as usize, true));
+        }
+
+        HSEM.ier(0).modify(|w| w.set_ise(id as usize, enabled))
+    })
+}
@@ -1,4 +1,5 @@
 use self::sealed::Instance;
+use crate::hsem::SemaphoreId;
 use embassy_stm32::{
     into_ref, peripherals::IPCC, rcc::low_level::RccPeripheral, Peripheral, PeripheralRef,
 };
@@ -44,6 +45,17 @@ impl From<IpccChannel> for usize {
     }
 }
 
+/// Raw IPCC registers of both processors, see [`Ipcc::registers`].
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct IpccRegisters {
+    pub c1_cr: u32,
+    pub c1_mr: u32,
+    pub c1_sr: u32,
+    pub c2_cr: u32,
+    pub c2_mr: u32,
+    pub c2_sr: u32,
+}
+
 pub(crate) mod sealed {
     use super::*;
 
@@ -70,8 +82,12 @@ impl<'d> Ipcc<'d> {
         IPCC::enable();
         IPCC::reset();
         IPCC::set_cpu2(true);
+        crate::hsem::enable();
 
+        // CPU2 may change the RCC configuration as well
+        crate::hsem::lock(SemaphoreId::Rcc);
         unsafe { _configure_pwr() };
+        crate::hsem::unlock(SemaphoreId::Rcc);
 
         let regs = IPCC::regs();
 
@@ -200,6 +216,22 @@ impl<'d> Ipcc<'d> {
     pub fn is_rx_pending(&self, channel: IpccChannel) -> bool {
         self.c2_is_active_flag(channel) && self.c1_get_rx_channel(channel)
     }
+
+    /// reads the control, mask and status registers of both processors
+    pub fn registers(&self) -> IpccRegisters {
+        let regs = IPCC::regs();
+
+        unsafe {
+            IpccRegisters {
+                c1_cr: regs.cpu(0).cr().read().0,
+                c1_mr: regs.cpu(0).mr().read().0,
+                c1_sr: regs.cpu(0).sr().read().0,
+                c2_cr: regs.cpu(1).cr().read().0,
+                c2_mr: regs.cpu(1).mr().read().0,
+                c2_sr: regs.cpu(1).sr().read().0,
+            }
+        }
+    }
 }
 
 impl sealed::Instance for embassy_stm32::peripherals::IPCC {
@@ -239,6 +271,8 @@ impl<'d> IpccExt<'d> for IPCC {
 /// * 64 MHz CPU1, 32 MHz CPU2
 /// * 64 MHz for APB1, APB2
 /// * HSI as a clock source after wake-up from low-power mode
+///
+/// The caller has to hold the [`SemaphoreId::Rcc`] semaphore.
 unsafe fn _configure_pwr() {
     let _pwr = embassy_stm32::pac::PWR;
     let rcc = embassy_stm32::pac::RCC;
@@ -1,4 +1,7 @@
 #![no_std]
+// the tests implement the async traits of `embedded-io`
+#![cfg_attr(test, feature(async_fn_in_trait))]
+#![cfg_attr(test, allow(incomplete_features))]
 
 #[macro_use]
 extern crate bitflags;
@@ -6,8 +9,20 @@ extern crate bitflags;
 extern crate bluetooth_hci;
 
 pub mod ble;
+pub mod bridge;
+pub mod concurrent;
+pub mod fem;
+pub mod flash;
+pub mod fus;
+pub mod gap;
+pub mod gatt;
+pub mod hal;
 pub mod hci;
+pub mod hsem;
 pub mod ipcc;
+pub mod lpm;
+pub mod nvm;
 mod pwr;
+pub mod shci;
 pub mod tl_mbox;
 mod unsafe_linked_list;
@@ -0,0 +1,244 @@
+//! Low-power modes of CPU1 while CPU2 is running.
+//!
+//! Both cores share the clock tree and each of them may enter a stop mode on its own, so ST's
+//! wireless firmware expects CPU1 to follow a few rules:
+//!
+//! - CPU2 has to be allowed to enter its low-power modes with
+//!   [`Shci::c2_radio_allow_low_power`](crate::shci::Shci::c2_radio_allow_low_power), otherwise
+//!   the radio keeps the system clock running,
+//! - the RCC configuration is only changed while holding the [`Rcc`](SemaphoreId::Rcc)
+//!   semaphore,
+//! - before entering a stop mode, CPU1 takes the [`StopMode`](SemaphoreId::StopMode) semaphore.
+//!   When CPU2 is already in deep sleep, the system clock is switched to HSI so that both cores
+//!   wake up on it,
+//! - on wakeup, the core that runs first restores the PLL clocked by HSE.
+//!
+//! [`LowPowerManager`] performs these steps and is meant to be called when the executor has no
+//! work left:
+//!
+//! ```ignore
+//! static LPM: LowPowerManager = LowPowerManager::new(LowPowerMode::Stop2);
+//!
+//! #[export_name = "__pender"]
+//! fn pender(_context: *mut ()) {
+//!     LPM.signal_work();
+//! }
+//!
+//! ble.shci().c2_radio_allow_low_power(RadioStack::Ble, true).await?;
+//!
+//! loop {
+//!     unsafe { executor.poll() };
+//!     LPM.idle();
+//! }
+//! ```
+//!
+//! The peripherals that wake CPU1 up (IPCC, RTC, LPTIM, EXTI) have to keep running in the
+//! selected mode. The embassy-time driver does not run in stop modes: hold a [`StopInhibitor`]
+//! while a timer is armed or a peripheral needs its clock, CPU1 then only enters sleep mode.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use crate::{
+    hsem::{self, SemaphoreId},
+    pwr,
+};
+
+/// Low-power modes CPU1 enters when it is idle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum LowPowerMode {
+    /// only the CPU1 clock is stopped
+    Sleep,
+    /// all clocks are stopped, the main regulator stays on
+    Stop0,
+    /// all clocks are stopped, the low-power regulator is used
+    Stop1,
+    /// all clocks are stopped and most of the peripherals are powered off
+    Stop2,
+}
+
+impl LowPowerMode {
+    /// returns the `LPMS` field of `PWR_CR1`
+    fn lpms(self) -> u8 {
+        match self {
+            LowPowerMode::Sleep | LowPowerMode::Stop0 => 0b000,
+            LowPowerMode::Stop1 => 0b001,
+            LowPowerMode::Stop2 => 0b010,
+        }
+    }
+}
+
+/// Puts CPU1 into a low-power mode when it is idle.
+pub struct LowPowerManager {
+    mode: LowPowerMode,
+    work: AtomicBool,
+    stop_inhibitors: AtomicU32,
+}
+
+impl LowPowerManager {
+    /// creates a manager that enters `mode` when idle
+    pub const fn new(mode: LowPowerMode) -> Self {
+        Self {
+            mode,
+            work: AtomicBool::new(false),
+            stop_inhibitors: AtomicU32::new(0),
+        }
+    }
+
+    /// records that a task was woken, so that the next call to [`LowPowerManager::idle`] returns
+    /// at once. Has to be called from the executor's pender.
+    pub fn signal_work(&self) {
+        self.work.store(true, Ordering::Release);
+    }
+
+    /// keeps CPU1 out of stop modes until the returned guard is dropped
+    pub fn inhibit_stop(&self) -> StopInhibitor<'_> {
+        self.stop_inhibitors.fetch_add(1, Ordering::AcqRel);
+
+        StopInhibitor { manager: self }
+    }
+
+    /// returns the mode CPU1 enters on the next call to [`LowPowerManager::idle`]
+    pub fn mode(&self) -> LowPowerMode {
+        if self.stop_inhibitors.load(Ordering::Acquire) > 0 {
+            LowPowerMode::Sleep
+        } else {
+            self.mode
+        }
+    }
+
+    /// enters the low-power mode until an interrupt is pending.
+    ///
+    /// Interrupt handlers run after the clock tree is restored.
+    pub fn idle(&self) {
+        let mode = self.mode();
+
+        cortex_m::interrupt::free(|_| {
+            if self.work.swap(false, Ordering::AcqRel) {
+                return;
+            }
+
+            if mode == LowPowerMode::Sleep {
+                cortex_m::asm::wfi();
+                return;
+            }
+
+            enter_stop(mode);
+            cortex_m::asm::wfi();
+            exit_stop();
+        })
+    }
+
+    /// enters standby, CPU1 then restarts from reset
+    pub fn standby(&self) -> ! {
+        cortex_m::interrupt::disable();
+
+        enter_low_power();
+        pwr::set_low_power_mode(0b011);
+
+        let mut scb = unsafe { cortex_m::Peripherals::steal() }.SCB;
+        scb.set_sleepdeep();
+
+        loop {
+            cortex_m::asm::wfi();
+        }
+    }
+}
+
+/// Keeps CPU1 out of stop modes while it lives, see [`LowPowerManager::inhibit_stop`].
+pub struct StopInhibitor<'a> {
+    manager: &'a LowPowerManager,
+}
+
+impl Drop for StopInhibitor<'_> {
+    fn drop(&mut self) {
+        self.manager.stop_inhibitors.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+fn enter_stop(mode: LowPowerMode) {
+    enter_low_power();
+
+    pwr::clear_c1_flags();
+    pwr::set_low_power_mode(mode.lpms());
+
+    let mut scb = unsafe { cortex_m::Peripherals::steal() }.SCB;
+    scb.set_sleepdeep();
+}
+
+fn exit_stop() {
+    let mut scb = unsafe { cortex_m::Peripherals::steal() }.SCB;
+    scb.clear_sleepdeep();
+
+    exit_low_power();
+}
+
+/// prepares the clock tree for a stop mode, `EnterLowPower` of ST's reference applications
+fn enter_low_power() {
+    hsem::lock(SemaphoreId::Rcc);
+
+    if hsem::try_lock(SemaphoreId::StopMode) {
+        // CPU2 wakes up first when it is not in deep sleep and restores the clock tree itself
+        if pwr::is_c2_deep_sleep() {
+            hsem::unlock(SemaphoreId::StopMode);
+            switch_on_hsi();
+        }
+    } else {
+        switch_on_hsi();
+    }
+
+    hsem::unlock(SemaphoreId::Rcc);
+}
+
+/// restores the clock tree after a stop mode, `ExitLowPower` of ST's reference applications
+fn exit_low_power() {
+    hsem::unlock(SemaphoreId::StopMode);
+    hsem::lock(SemaphoreId::Rcc);
+
+    // CPU2 may have restored the clock tree already
+    if system_clock_source() != SYSCLK_PLL {
+        restore_pll();
+    }
+
+    hsem::unlock(SemaphoreId::Rcc);
+}
+
+const SYSCLK_HSI: u8 = 0b01;
+const SYSCLK_PLL: u8 = 0b11;
+
+fn system_clock_source() -> u8 {
+    unsafe { embassy_stm32::pac::RCC.cfgr().read().sws() }
+}
+
+/// runs the system on HSI, which the cores wake up on
+fn switch_on_hsi() {
+    let rcc = embassy_stm32::pac::RCC;
+
+    unsafe {
+        rcc.cr().modify(|w| w.set_hsion(true));
+        while !rcc.cr().read().hsirdy() {}
+
+        rcc.cfgr().modify(|w| w.set_sw(SYSCLK_HSI));
+        while rcc.cfgr().read().sws() != SYSCLK_HSI {}
+
+        embassy_stm32::pac::FLASH.acr().modify(|w| w.set_latency(0));
+    }
+}
+
+/// runs the system on the PLL clocked by HSE again, as configured by [`crate::ipcc::Ipcc::init`]
+fn restore_pll() {
+    let rcc = embassy_stm32::pac::RCC;
+
+    unsafe {
+        rcc.cr().modify(|w| w.set_hseon(true));
+        while !rcc.cr().read().hserdy() {}
+
+        rcc.cr().modify(|w| w.set_pllon(true));
+        while !rcc.cr().read().pllrdy() {}
+
+        // 64 MHz needs 3 wait states
+        embassy_stm32::pac::FLASH.acr().modify(|w| w.set_latency(3));
+
+        rcc.cfgr().modify(|w| w.set_sw(SYSCLK_PLL));
+        while rcc.cfgr().read().sws() != SYSCLK_PLL {}
+    }
+}
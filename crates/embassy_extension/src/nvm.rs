@@ -0,0 +1,487 @@
+//! Persistence of the BLE stack NVM data.
+//!
+//! CPU2 keeps the bonding and security database of the BLE stack in NVM. When it is configured
+//! to keep this data in SRAM, it reports every update with a
+//! [`BleNvmRamUpdate`](SysEvent::BleNvmRamUpdate) system event and CPU1 is responsible for
+//! writing the buffer to flash, so that bonds survive a reset.
+//!
+//! [`BleNvm`] restores the buffer from a [`NorFlash`] backend at boot, provides the config
+//! command parameters pointing CPU2 to the buffer and writes the buffer back on every update.
+//! CPU2 is running by then, so the buffer is written through a [`FlashGuard`], which performs the
+//! handshake of [`crate::flash`] around each erase and program operation:
+//!
+//! ```ignore
+//! let mut nvm = BleNvm::new(flash, NVM_OFFSET)?;
+//! nvm.restore()?;
+//!
+//! let mut ble = Ble::init_with_c2_config(rx, tx, config, Some(nvm.c2_config()), mbox, ipcc)
+//!     .await?;
+//!
+//! loop {
+//!     if let SysEvent::BleNvmRamUpdate(_) = ble.receive_sys_event().await? {
+//!         let mut guard = ble.flash_guard(&hsem).await?;
+//!         nvm.persist_guarded(&mut guard).await?;
+//!     }
+//! }
+//! ```
+//!
+//! [`BleNvm::persist`] and [`BleNvm::process_sys_event`] access the backend directly, they are
+//! only safe while CPU2 is stopped or with a backend that performs the handshake itself.
+//!
+//! The flash region starts with a header that is written last, so that an interrupted write
+//! is detected at the next boot instead of restoring a partial database.
+
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+
+use crate::{
+    flash::{FlashGuard, FlashGuardError},
+    shci::event::SysEvent,
+    tl_mbox::{
+        shci::{config1, evt_mask1, ShciConfigParam, SHCI_C2_CONFIG_PAYLOAD_CMD_SIZE},
+        BLE_NVM_SRAM, BLE_NVM_SRAM_SIZE,
+    },
+};
+
+/// Size of the BLE NVM data, in bytes.
+pub const BLE_NVM_SIZE: usize = BLE_NVM_SRAM_SIZE * 4;
+
+const MAGIC: u32 = 0x4d56_4e42; // "BNVM"
+const HEADER_SIZE: usize = 16;
+/// Flash accesses go through a buffer of this size.
+const CHUNK_SIZE: usize = 256;
+
+/// Errors that may occur when persisting or restoring the BLE NVM data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NvmError<E> {
+    /// The flash backend failed.
+    Flash(E),
+
+    /// The offset of the region is not aligned to the erase size of the flash.
+    Misaligned,
+
+    /// The region does not fit in the flash.
+    TooSmall,
+}
+
+/// Keeps the BLE NVM data of CPU2 in a region of a [`NorFlash`].
+pub struct BleNvm<F> {
+    flash: F,
+    offset: u32,
+}
+
+impl<F: NorFlash> BleNvm<F> {
+    /// Size of the flash region used to store the data, in bytes.
+    pub const REGION_SIZE: usize = round_up(HEADER_SIZE + BLE_NVM_SIZE, F::ERASE_SIZE);
+
+    /// The header and the chunks are read and written whole, a backend with larger read or write
+    /// units fails to compile.
+    const UNITS_FIT: () = assert!(
+        CHUNK_SIZE % F::READ_SIZE == 0
+            && CHUNK_SIZE % F::WRITE_SIZE == 0
+            && HEADER_SIZE % F::READ_SIZE == 0
+            && HEADER_SIZE % F::WRITE_SIZE == 0,
+        "the read and write sizes of the flash have to divide the header and chunk sizes"
+    );
+
+    /// uses the region of `flash` starting at `offset`, which has to be aligned to the erase
+    /// size of the flash and to span [`BleNvm::REGION_SIZE`] bytes.
+    pub fn new(flash: F, offset: u32) -> Result<Self, NvmError<F::Error>> {
+        // evaluates the assertion, at compile time
+        #[allow(clippy::let_unit_value)]
+        let () = Self::UNITS_FIT;
+
+        if offset as usize % F::ERASE_SIZE != 0 {
+            return Err(NvmError::Misaligned);
+        }
+        if offset as usize + Self::REGION_SIZE > flash.capacity() {
+            return Err(NvmError::TooSmall);
+        }
+
+        Ok(Self { flash, offset })
+    }
+
+    /// returns the config command parameters that make CPU2 keep the BLE NVM data in SRAM and
+    /// report its updates
+    pub fn c2_config(&self) -> ShciConfigParam {
+        ShciConfigParam {
+            payload_cmd_size: SHCI_C2_CONFIG_PAYLOAD_CMD_SIZE,
+            config1: config1::BLE_NVM_DATA_TO_SRAM,
+            evt_mask1: evt_mask1::ERROR_NOTIF_ENABLE | evt_mask1::BLE_NVM_RAM_UPDATE_ENABLE,
+            ble_nvm_ram_address: unsafe { BLE_NVM_SRAM.as_ptr() } as u32,
+            ..Default::default()
+        }
+    }
+
+    /// loads the data last written to flash into the SRAM buffer.
+    ///
+    /// Has to be called before CPU2 starts the BLE stack. Returns `false` if the flash holds no
+    /// valid data, the buffer is then left erased and the BLE stack starts without bonds.
+    pub fn restore(&mut self) -> Result<bool, NvmError<F::Error>> {
+        let buffer = unsafe { nvm_sram() };
+        buffer.fill(0xff);
+
+        let mut header = [0u8; HEADER_SIZE];
+        self.flash
+            .read(self.offset, &mut header)
+            .map_err(NvmError::Flash)?;
+
+        let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let checksum = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+
+        if magic != MAGIC || size as usize != BLE_NVM_SIZE {
+            defmt::debug!("no BLE NVM data in flash");
+            return Ok(false);
+        }
+
+        let mut chunk = [0u8; CHUNK_SIZE];
+        let mut offset = self.offset + HEADER_SIZE as u32;
+        for data in buffer.chunks_mut(CHUNK_SIZE) {
+            let len = round_up(data.len(), F::READ_SIZE);
+            self.flash
+                .read(offset, &mut chunk[..len])
+                .map_err(NvmError::Flash)?;
+
+            data.copy_from_slice(&chunk[..data.len()]);
+            offset += len as u32;
+        }
+
+        if fnv1a(buffer) != checksum {
+            defmt::warn!("BLE NVM data in flash is corrupted");
+            buffer.fill(0xff);
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// writes the SRAM buffer to flash, without the handshake with CPU2, see
+    /// [`BleNvm::persist_guarded`]
+    pub fn persist(&mut self) -> Result<(), NvmError<F::Error>> {
+        let buffer = unsafe { nvm_sram() };
+
+        self.flash
+            .erase(self.offset, self.offset + Self::REGION_SIZE as u32)
+            .map_err(NvmError::Flash)?;
+
+        let mut chunk = [0u8; CHUNK_SIZE];
+        let mut offset = self.offset + HEADER_SIZE as u32;
+        for data in buffer.chunks(CHUNK_SIZE) {
+            let len = pad_chunk::<F>(data, &mut chunk);
+            self.flash
+                .write(offset, &chunk[..len])
+                .map_err(NvmError::Flash)?;
+            offset += len as u32;
+        }
+
+        self.flash
+            .write(self.offset, &header(buffer))
+            .map_err(NvmError::Flash)
+    }
+
+    /// writes the SRAM buffer to flash while CPU2 is running.
+    ///
+    /// Each page is erased and each write unit of the backend is programmed between two
+    /// timing-critical windows of CPU2.
+    pub async fn persist_guarded(
+        &mut self,
+        guard: &mut FlashGuard<'_>,
+    ) -> Result<(), FlashGuardError<F::Error>> {
+        let buffer = unsafe { nvm_sram() };
+        let flash = &mut self.flash;
+
+        let region = self.offset..self.offset + Self::REGION_SIZE as u32;
+        guard
+            .erase(region.step_by(F::ERASE_SIZE), |page| {
+                flash.erase(page, page + F::ERASE_SIZE as u32)
+            })
+            .await?;
+
+        let mut chunk = [0u8; CHUNK_SIZE];
+        let mut offset = self.offset + HEADER_SIZE as u32;
+        for data in buffer.chunks(CHUNK_SIZE) {
+            let len = pad_chunk::<F>(data, &mut chunk);
+            for unit in chunk[..len].chunks(F::WRITE_SIZE) {
+                guard
+                    .program(|| flash.write(offset, unit))
+                    .await
+                    .map_err(FlashGuardError::Flash)?;
+                offset += unit.len() as u32;
+            }
+        }
+
+        let header = header(buffer);
+        let mut offset = self.offset;
+        for unit in header.chunks(F::WRITE_SIZE) {
+            guard
+                .program(|| flash.write(offset, unit))
+                .await
+                .map_err(FlashGuardError::Flash)?;
+            offset += unit.len() as u32;
+        }
+
+        Ok(())
+    }
+
+    /// persists the SRAM buffer if `event` reports that CPU2 updated it, without the handshake
+    /// with CPU2, see [`BleNvm::persist_guarded`].
+    ///
+    /// Returns `true` if the buffer was written to flash.
+    pub fn process_sys_event(&mut self, event: &SysEvent) -> Result<bool, NvmError<F::Error>> {
+        match event {
+            SysEvent::BleNvmRamUpdate(update) => {
+                defmt::debug!("BLE NVM update: {}", update);
+                self.persist()?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// releases the flash backend
+    pub fn release(self) -> F {
+        self.flash
+    }
+}
+
+/// copies `data` into `chunk`, padded to the write size of the flash, and returns the padded
+/// length
+fn pad_chunk<F: NorFlash>(data: &[u8], chunk: &mut [u8; CHUNK_SIZE]) -> usize {
+    let len = round_up(data.len(), F::WRITE_SIZE);
+    chunk[..data.len()].copy_from_slice(data);
+    chunk[data.len()..len].fill(0xff);
+
+    len
+}
+
+/// returns the header describing `buffer`
+fn header(buffer: &[u8]) -> [u8; HEADER_SIZE] {
+    let mut header = [0xffu8; HEADER_SIZE];
+    header[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+    header[4..8].copy_from_slice(&(BLE_NVM_SIZE as u32).to_le_bytes());
+    header[8..12].copy_from_slice(&fnv1a(buffer).to_le_bytes());
+
+    header
+}
+
+unsafe fn nvm_sram() -> &'static mut [u8] {
+    core::slice::from_raw_parts_mut(BLE_NVM_SRAM.as_mut_ptr().cast(), BLE_NVM_SIZE)
+}
+
+const fn round_up(value: usize, align: usize) -> usize {
+    (value + align - 1) / align * align
+}
+
+fn fnv1a(data: &[u8]) -> u32 {
+    data.iter()
+        .fold(0x811c_9dc5, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x0100_0193))
+}
+
+/// RAM-backed [`NorFlash`] with the geometry of the STM32WB5x flash, to exercise [`BleNvm`]
+/// without hardware.
+///
+/// `N` has to be a multiple of the erase size.
+pub struct RamFlash<const N: usize> {
+    data: [u8; N],
+}
+
+impl<const N: usize> RamFlash<N> {
+    /// creates an erased flash
+    pub const fn new() -> Self {
+        Self { data: [0xff; N] }
+    }
+
+    /// returns the content of the flash
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn check(&self, offset: u32, len: usize, align: usize) -> Result<(), NorFlashErrorKind> {
+        if offset as usize % align != 0 || len % align != 0 {
+            Err(NorFlashErrorKind::NotAligned)
+        } else if offset as usize + len > N {
+            Err(NorFlashErrorKind::OutOfBounds)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<const N: usize> Default for RamFlash<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> ErrorType for RamFlash<N> {
+    type Error = NorFlashErrorKind;
+}
+
+impl<const N: usize> ReadNorFlash for RamFlash<N> {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.check(offset, bytes.len(), Self::READ_SIZE)?;
+
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> NorFlash for RamFlash<N> {
+    const WRITE_SIZE: usize = 8;
+    const ERASE_SIZE: usize = 4096;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if to < from {
+            return Err(NorFlashErrorKind::OutOfBounds);
+        }
+        self.check(from, (to - from) as usize, Self::ERASE_SIZE)?;
+
+        self.data[from as usize..to as usize].fill(0xff);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.check(offset, bytes.len(), Self::WRITE_SIZE)?;
+
+        // like NOR flash, a write can only clear bits
+        let offset = offset as usize;
+        for (cell, byte) in self.data[offset..offset + bytes.len()]
+            .iter_mut()
+            .zip(bytes)
+        {
+            *cell &= *byte;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::*;
+    use crate::shci::event::NvmRamUpdate;
+
+    type Flash = RamFlash<{ 2 * 4096 }>;
+
+    const OFFSET: u32 = 4096;
+
+    /// the tests share the SRAM buffer of CPU2
+    static SRAM: Mutex<()> = Mutex::new(());
+
+    fn lock_sram() -> MutexGuard<'static, ()> {
+        SRAM.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn fill_sram() {
+        let buffer = unsafe { nvm_sram() };
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+    }
+
+    fn sram_is_filled() -> bool {
+        let buffer = unsafe { nvm_sram() };
+        buffer.iter().enumerate().all(|(i, byte)| *byte == i as u8)
+    }
+
+    fn sram_is_erased() -> bool {
+        unsafe { nvm_sram() }.iter().all(|byte| *byte == 0xff)
+    }
+
+    #[test]
+    fn persist_restore_round_trip() {
+        let _sram = lock_sram();
+
+        let mut nvm = BleNvm::new(Flash::new(), OFFSET).unwrap();
+        fill_sram();
+        nvm.persist().unwrap();
+
+        unsafe { nvm_sram() }.fill(0);
+        assert_eq!(nvm.restore(), Ok(true));
+        assert!(sram_is_filled());
+
+        // the region before the offset is left alone
+        let flash = nvm.release();
+        assert!(flash.data()[..OFFSET as usize]
+            .iter()
+            .all(|byte| *byte == 0xff));
+    }
+
+    #[test]
+    fn restore_from_blank_flash() {
+        let _sram = lock_sram();
+
+        let mut nvm = BleNvm::new(Flash::new(), OFFSET).unwrap();
+        fill_sram();
+
+        assert_eq!(nvm.restore(), Ok(false));
+        assert!(sram_is_erased());
+    }
+
+    #[test]
+    fn restore_corrupted_image() {
+        let _sram = lock_sram();
+
+        let mut nvm = BleNvm::new(Flash::new(), OFFSET).unwrap();
+        fill_sram();
+        nvm.persist().unwrap();
+
+        // clear bits of the data after the header was written
+        let mut flash = nvm.release();
+        flash
+            .write(OFFSET + HEADER_SIZE as u32 + 64, &[0; 8])
+            .unwrap();
+
+        let mut nvm = BleNvm::new(flash, OFFSET).unwrap();
+        assert_eq!(nvm.restore(), Ok(false));
+        assert!(sram_is_erased());
+    }
+
+    #[test]
+    fn restore_truncated_image() {
+        let _sram = lock_sram();
+
+        // a persist interrupted before the header was written
+        let mut flash = Flash::new();
+        flash
+            .write(OFFSET + HEADER_SIZE as u32, &[0x5a; 64])
+            .unwrap();
+
+        let mut nvm = BleNvm::new(flash, OFFSET).unwrap();
+        fill_sram();
+        assert_eq!(nvm.restore(), Ok(false));
+        assert!(sram_is_erased());
+    }
+
+    #[test]
+    fn process_nvm_ram_update() {
+        let _sram = lock_sram();
+
+        let mut nvm = BleNvm::new(Flash::new(), OFFSET).unwrap();
+        fill_sram();
+
+        assert_eq!(nvm.process_sys_event(&SysEvent::NvmEndWrite), Ok(false));
+        assert_eq!(nvm.restore(), Ok(false));
+
+        fill_sram();
+        let update = SysEvent::BleNvmRamUpdate(NvmRamUpdate {
+            start_address: unsafe { BLE_NVM_SRAM.as_ptr() } as u32,
+            size: BLE_NVM_SIZE as u32,
+        });
+        assert_eq!(nvm.process_sys_event(&update), Ok(true));
+
+        unsafe { nvm_sram() }.fill(0);
+        assert_eq!(nvm.restore(), Ok(true));
+        assert!(sram_is_filled());
+    }
+}
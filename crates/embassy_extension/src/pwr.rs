@@ -9,3 +9,25 @@ pub fn set_backup_access(enabled: bool) {
         pwr.cr1().modify(|w| w.set_dbp(enabled));
     }
 }
+
+/// selects the low-power mode CPU1 enters when it goes to deep sleep, `lpms` is the `LPMS`
+/// field of `PWR_CR1`
+pub fn set_low_power_mode(lpms: u8) {
+    unsafe { embassy_stm32::pac::PWR.cr1().modify(|w| w.set_lpms(lpms)) }
+}
+
+/// returns `true` if CPU2 is in deep sleep or in standby
+pub fn is_c2_deep_sleep() -> bool {
+    let extscr = unsafe { embassy_stm32::pac::PWR.extscr().read() };
+
+    extscr.c2ds() || extscr.c2sbf()
+}
+
+/// clears the stop and standby flags of CPU1
+pub fn clear_c1_flags() {
+    unsafe {
+        embassy_stm32::pac::PWR
+            .extscr()
+            .write(|w| w.set_c1cssf(true))
+    }
+}
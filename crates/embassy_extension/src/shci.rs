@@ -0,0 +1,179 @@
+//! System commands (SHCI) of the STM32WB5x radio coprocessor.
+//!
+//! System commands configure CPU2 itself rather than the BLE controller: they start the wireless
+//! stacks (BLE, Thread, Zigbee, 802.15.4 MAC), configure CPU2 and query its state. They are sent
+//! on the system channel and CPU2 answers each of them with a command complete event that holds
+//! a status and optional return parameters.
+//!
+//! CPU2 also reports system events on the system channel, see [`event`].
+
+use crate::{
+    ble::{Ble, BleError, BleTransportLayerError},
+    shci::ble_init::BleInitConfig,
+    tl_mbox::shci::{
+        param_bytes, ConcurrentMode, FlashActivityControl, RadioStack, ShciConfigParam,
+        ShciExtpaConfigParam, ShciOpcode, ShciResponse, ShciStatus,
+    },
+};
+
+pub mod ble_init;
+pub mod event;
+pub mod gatt_db;
+
+/// handle for sending system commands, see [`Ble::shci`]
+pub struct Shci<'a> {
+    ble: &'a mut Ble,
+}
+
+impl<'a> Shci<'a> {
+    pub(crate) fn new(ble: &'a mut Ble) -> Self {
+        Self { ble }
+    }
+
+    /// sends any SHCI command and awaits its response.
+    ///
+    /// Returns the response even if CPU2 reported an error.
+    pub async fn command(
+        &mut self,
+        opcode: ShciOpcode,
+        payload: &[u8],
+    ) -> Result<ShciResponse, BleError<BleTransportLayerError>> {
+        self.ble.perform_shci_command(opcode as u16, payload).await
+    }
+
+    /// sends an SHCI command and fails if CPU2 did not report success
+    async fn checked_command(
+        &mut self,
+        opcode: ShciOpcode,
+        payload: &[u8],
+    ) -> Result<ShciResponse, BleError<BleTransportLayerError>> {
+        let response = self.command(opcode, payload).await?;
+
+        match response.status {
+            ShciStatus::Success => Ok(response),
+            status => Err(BleError::ShciError(status)),
+        }
+    }
+
+    /// starts the BLE stack.
+    ///
+    /// [`Ble::init`] and [`Ble::start_ble`] already start the BLE stack and make
+    /// [`Ble::perform_command`] available, this only sends the command.
+    pub async fn c2_ble_init(
+        &mut self,
+        config: impl Into<BleInitConfig>,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.checked_command(ShciOpcode::BleInit, config.into().bytes())
+            .await
+            .map(|_| ())
+    }
+
+    /// starts the Thread stack
+    pub async fn c2_thread_init(&mut self) -> Result<(), BleError<BleTransportLayerError>> {
+        self.checked_command(ShciOpcode::ThreadInit, &[])
+            .await
+            .map(|_| ())
+    }
+
+    /// starts the Zigbee stack
+    pub async fn c2_zigbee_init(&mut self) -> Result<(), BleError<BleTransportLayerError>> {
+        self.checked_command(ShciOpcode::ZigbeeInit, &[])
+            .await
+            .map(|_| ())
+    }
+
+    /// starts the 802.15.4 MAC stack
+    pub async fn c2_mac_802_15_4_init(&mut self) -> Result<(), BleError<BleTransportLayerError>> {
+        self.checked_command(ShciOpcode::Mac802_15_4Init, &[])
+            .await
+            .map(|_| ())
+    }
+
+    /// configures CPU2, e.g. where the BLE stack keeps its NVM data and which system events
+    /// it reports
+    pub async fn c2_config(
+        &mut self,
+        param: ShciConfigParam,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.checked_command(ShciOpcode::Config, param_bytes(&param))
+            .await
+            .map(|_| ())
+    }
+
+    /// restarts the wireless stack running on CPU2
+    pub async fn c2_reinit(&mut self) -> Result<(), BleError<BleTransportLayerError>> {
+        self.checked_command(ShciOpcode::Reinit, &[])
+            .await
+            .map(|_| ())
+    }
+
+    /// tells CPU2 that CPU1 is about to erase the flash, so that CPU2 schedules its radio
+    /// activity around the erase operations, see [`crate::flash::FlashGuard`]
+    pub async fn c2_flash_erase_activity(
+        &mut self,
+        active: bool,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.checked_command(ShciOpcode::FlashEraseActivity, &[active as u8])
+            .await
+            .map(|_| ())
+    }
+
+    /// selects how CPU2 blocks flash operations of CPU1 during its timing-critical windows
+    pub async fn c2_set_flash_activity_control(
+        &mut self,
+        control: FlashActivityControl,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.checked_command(ShciOpcode::SetFlashActivityControl, &[control as u8])
+            .await
+            .map(|_| ())
+    }
+
+    /// allows or forbids CPU2 to enter low-power modes while `stack` is running, see
+    /// [`crate::lpm`]
+    pub async fn c2_radio_allow_low_power(
+        &mut self,
+        stack: RadioStack,
+        allow: bool,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.checked_command(ShciOpcode::RadioAllowLowPower, &[stack as u8, allow as u8])
+            .await
+            .map(|_| ())
+    }
+
+    /// gives the radio to `mode` when running a concurrent firmware, see [`crate::concurrent`]
+    pub async fn c2_concurrent_set_mode(
+        &mut self,
+        mode: ConcurrentMode,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.checked_command(ShciOpcode::ConcurrentSetMode, &[mode as u8])
+            .await
+            .map(|_| ())
+    }
+
+    /// configures the GPIO through which CPU2 controls an external PA, see [`crate::fem`]
+    pub async fn c2_extpa_config(
+        &mut self,
+        param: ShciExtpaConfigParam,
+    ) -> Result<(), BleError<BleTransportLayerError>> {
+        self.checked_command(ShciOpcode::ExtpaConfig, param_bytes(&param))
+            .await
+            .map(|_| ())
+    }
+
+    /// returns the time until the next BLE radio event, in microseconds.
+    ///
+    /// Used to schedule another radio protocol in between BLE events when running concurrent
+    /// stacks.
+    pub async fn c2_get_next_ble_evt_time(
+        &mut self,
+    ) -> Result<u32, BleError<BleTransportLayerError>> {
+        let response = self
+            .checked_command(ShciOpcode::ConcurrentGetNextBleEvtTime, &[])
+            .await?;
+
+        match response.payload() {
+            [b0, b1, b2, b3, ..] => Ok(u32::from_le_bytes([*b0, *b1, *b2, *b3])),
+            _ => Err(BleError::UnexpectedEvent),
+        }
+    }
+}
@@ -0,0 +1,497 @@
+//! Parameters of the BLE init system command.
+//!
+//! The BLE stack sizes its memory pools from the number of links and the ATT MTU, and newer
+//! stacks read more parameters than older ones. [`BleInitBuilder`] derives the pool sizes the way
+//! ST's `ble_bufsize.h` does, checks the parameters and produces a [`BleInitConfig`] holding the
+//! bytes expected by the targeted stack version:
+//!
+//! ```ignore
+//! let config = BleInitBuilder::new(BleStackVersion::V1_13)
+//!     .num_links(2)
+//!     .att_mtu(156)
+//!     .options(BleInitOptions::DEVICE_NAME_RO)
+//!     .build()?;
+//!
+//! let ble = Ble::init(rx_irq, tx_irq, config, mbox, ipcc).await?;
+//! ```
+
+use crate::{
+    shci::gatt_db::GattDbSize,
+    tl_mbox::shci::{param_bytes, ShciBleInitCmdParam},
+};
+
+/// Version of the BLE stack running on CPU2, selects the parameters sent with the BLE init
+/// command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum BleStackVersion {
+    /// up to the hardware version
+    V1_11,
+    /// adds the number of CoC initiators, the TX power range and the RX model
+    V1_12,
+    /// adds the advertising sets and the path compensations
+    V1_13,
+    /// adds the Bluetooth core version
+    V1_15,
+}
+
+impl BleStackVersion {
+    /// Number of parameter bytes read by the stack.
+    pub const fn param_len(self) -> usize {
+        match self {
+            BleStackVersion::V1_11 => 33,
+            BleStackVersion::V1_12 => 37,
+            BleStackVersion::V1_13 => 44,
+            BleStackVersion::V1_15 => 45,
+        }
+    }
+}
+
+bitflags! {
+    /// Options of the BLE stack, see [`BleInitBuilder::options`].
+    pub struct BleInitOptions: u8 {
+        /// Only the link layer runs on CPU2, the host runs on CPU1.
+        const LL_ONLY = 0x01;
+        /// The service changed characteristic has no description.
+        const NO_SVC_CHANGE_DESC = 0x02;
+        /// The device name characteristic is read-only.
+        const DEVICE_NAME_RO = 0x04;
+        /// Extended advertising is supported (v1.13).
+        const EXT_ADV = 0x08;
+        /// Channel selection algorithm #2 is supported.
+        const CS_ALGO2 = 0x10;
+        /// Only the security database is kept in NVM.
+        const REDUCED_DB_IN_NVM = 0x20;
+        /// GATT caching is supported.
+        const GATT_CACHING = 0x40;
+        /// The TX power is set for power class 1 devices.
+        const POWER_CLASS_1 = 0x80;
+    }
+}
+
+/// Low-speed clock used by the BLE stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum LsSource {
+    /// external 32.768 kHz crystal
+    Lse = 0,
+    /// internal RO oscillator, calibrated by the stack
+    InternalRo = 1,
+}
+
+/// Bluetooth core version reported by the BLE stack (v1.15).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum BleCoreVersion {
+    V5_0 = 9,
+    V5_1 = 10,
+    V5_2 = 11,
+    V5_3 = 12,
+}
+
+/// Errors reported by [`BleInitBuilder::build`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum BleInitError {
+    /// The number of links is not in `1..=8`.
+    BadNumLinks(u8),
+
+    /// The ATT MTU is not in `23..=512`, or above 251 before v1.13.
+    BadAttMtu(u16),
+
+    /// The number of advertising sets is not in `1..=8`.
+    BadAdvSetNumber(u8),
+
+    /// The advertising data length is not in `31..=1650`.
+    BadAdvDataLength(u16),
+
+    /// A path compensation is not in `-1280..=1280`.
+    BadPathCompensation(i16),
+
+    /// The minimum TX power is above the maximum TX power.
+    BadTxPowerRange(i8, i8),
+
+    /// The GATT database was sized for fewer links than configured. Includes the number of
+    /// links it was sized for.
+    GattDbLinks(u8),
+
+    /// The memory blocks derived from the parameters do not fit the 8-bit parameters of the
+    /// command.
+    TooManyMemoryBlocks(u32),
+
+    /// The parameter is not supported by the targeted stack version. Includes the parameter
+    /// name and the first version that supports it.
+    Unsupported(&'static str, BleStackVersion),
+}
+
+/// BLE init command parameters for a given stack version, built with [`BleInitBuilder`].
+#[derive(Debug, Clone, Copy)]
+pub struct BleInitConfig {
+    param: ShciBleInitCmdParam,
+    version: BleStackVersion,
+}
+
+impl BleInitConfig {
+    /// returns the parameters of the command
+    pub fn param(&self) -> &ShciBleInitCmdParam {
+        &self.param
+    }
+
+    /// returns the version of the targeted stack
+    pub fn version(&self) -> BleStackVersion {
+        self.version
+    }
+
+    /// returns the bytes sent to CPU2
+    pub fn bytes(&self) -> &[u8] {
+        &param_bytes(&self.param)[..self.version.param_len()]
+    }
+}
+
+/// Sends the fields of raw parameters known to the oldest supported stack version, use
+/// [`BleInitBuilder`] to target a newer one.
+impl From<ShciBleInitCmdParam> for BleInitConfig {
+    fn from(param: ShciBleInitCmdParam) -> Self {
+        Self {
+            param,
+            version: BleStackVersion::V1_11,
+        }
+    }
+}
+
+const BLE_DEFAULT_ATT_MTU: u16 = 23;
+const BLE_MEM_BLOCK_SIZE: u32 = 32;
+const BLE_MBLOCKS_SECURE_CONNECTIONS: u32 = 4;
+const MAX_LINKS: u8 = 8;
+const MAX_ADV_SETS: u8 = 8;
+const MAX_PATH_COMPENSATION: i16 = 1280;
+
+/// Builder for [`BleInitConfig`].
+///
+/// The defaults are those of ST's reference applications.
+#[derive(Debug, Clone, Copy)]
+pub struct BleInitBuilder {
+    version: BleStackVersion,
+    param: ShciBleInitCmdParam,
+    prepare_write_list_size: Option<u8>,
+    mblock_count: Option<u8>,
+    gatt_db_links: Option<u8>,
+}
+
+impl BleInitBuilder {
+    /// starts from ST's defaults: 8 links and an ATT MTU of 156 bytes
+    pub fn new(version: BleStackVersion) -> Self {
+        Self {
+            version,
+            param: ShciBleInitCmdParam {
+                p_ble_buffer_address: 0,
+                ble_buffer_size: 0,
+                num_attr_record: 68,
+                num_attr_serv: 8,
+                attr_value_arr_size: 1344,
+                num_of_links: MAX_LINKS,
+                extended_packet_length_enable: 1,
+                pr_write_list_size: 0,
+                mb_lock_count: 0,
+                att_mtu: 156,
+                slave_sca: 500,
+                master_sca: 0,
+                ls_source: LsSource::InternalRo as u8,
+                max_conn_event_length: 0xffff_ffff,
+                hs_startup_time: 0x148,
+                viterbi_enable: 1,
+                ll_only: 0,
+                hw_version: 0,
+                max_coc_initiator_nbr: 32,
+                min_tx_power: -40,
+                max_tx_power: 6,
+                rx_model_config: 0,
+                max_adv_set_nbr: 3,
+                max_adv_data_len: 1650,
+                tx_path_compens: 0,
+                rx_path_compens: 0,
+                ble_core_version: BleCoreVersion::V5_3 as u8,
+            },
+            prepare_write_list_size: None,
+            mblock_count: None,
+            gatt_db_links: None,
+        }
+    }
+
+    /// preset for a peripheral with a single link and the default ATT MTU, which needs the
+    /// least memory on CPU2
+    pub fn single_link(version: BleStackVersion) -> Self {
+        Self::new(version).num_links(1).att_mtu(BLE_DEFAULT_ATT_MTU)
+    }
+
+    /// preset for the highest throughput: the largest ATT MTU a single LL packet carries and
+    /// data length extension
+    pub fn high_throughput(version: BleStackVersion) -> Self {
+        Self::new(version).att_mtu(251).extended_packet_length(true)
+    }
+
+    /// number of simultaneous links
+    pub fn num_links(mut self, num_links: u8) -> Self {
+        self.param.num_of_links = num_links;
+        self
+    }
+
+    /// largest ATT MTU, in bytes
+    pub fn att_mtu(mut self, att_mtu: u16) -> Self {
+        self.param.att_mtu = att_mtu;
+        self
+    }
+
+    /// number of GATT attributes, services and size of the attribute values, in bytes
+    pub fn gatt_db(
+        mut self,
+        num_attr_record: u16,
+        num_attr_serv: u16,
+        attr_value_arr_size: u16,
+    ) -> Self {
+        self.param.num_attr_record = num_attr_record;
+        self.param.num_attr_serv = num_attr_serv;
+        self.param.attr_value_arr_size = attr_value_arr_size;
+        self
+    }
+
+    /// GATT database parameters computed by [`GattDb::size`](crate::shci::gatt_db::GattDb::size)
+    pub fn gatt_db_size(mut self, size: GattDbSize) -> Self {
+        self = self.gatt_db(size.num_attr_record, size.num_attr_serv, size.attr_value_arr_size);
+        self.gatt_db_links = Some(size.num_links);
+        self
+    }
+
+    /// enables data length extension
+    pub fn extended_packet_length(mut self, enabled: bool) -> Self {
+        self.param.extended_packet_length_enable = enabled as u8;
+        self
+    }
+
+    /// overrides the number of prepare write requests, derived from the ATT MTU by default
+    pub fn prepare_write_list_size(mut self, size: u8) -> Self {
+        self.prepare_write_list_size = Some(size);
+        self
+    }
+
+    /// overrides the number of memory blocks, derived from the number of links and the ATT MTU
+    /// by default
+    pub fn mblock_count(mut self, count: u8) -> Self {
+        self.mblock_count = Some(count);
+        self
+    }
+
+    /// sleep clock accuracy of the peripheral and of the central, in ppm and as the BLE SCA
+    /// field
+    pub fn sleep_clock_accuracy(mut self, peripheral_ppm: u16, central_sca: u8) -> Self {
+        self.param.slave_sca = peripheral_ppm;
+        self.param.master_sca = central_sca;
+        self
+    }
+
+    /// low-speed clock used by the stack
+    pub fn ls_source(mut self, source: LsSource) -> Self {
+        self.param.ls_source = source as u8;
+        self
+    }
+
+    /// longest connection event, in microseconds
+    pub fn max_conn_event_length(mut self, us: u32) -> Self {
+        self.param.max_conn_event_length = us;
+        self
+    }
+
+    /// HSE startup time, in units of 625/256 µs
+    pub fn hs_startup_time(mut self, time: u16) -> Self {
+        self.param.hs_startup_time = time;
+        self
+    }
+
+    /// enables the Viterbi decoder for the LE coded PHY
+    pub fn viterbi(mut self, enabled: bool) -> Self {
+        self.param.viterbi_enable = enabled as u8;
+        self
+    }
+
+    /// options of the stack
+    pub fn options(mut self, options: BleInitOptions) -> Self {
+        self.param.ll_only = options.bits();
+        self
+    }
+
+    /// number of L2CAP connection-oriented channels as initiator (v1.12)
+    pub fn max_coc_initiator_nbr(mut self, number: u8) -> Self {
+        self.param.max_coc_initiator_nbr = number;
+        self
+    }
+
+    /// range of the TX power, in dBm (v1.12)
+    pub fn tx_power_range(mut self, min: i8, max: i8) -> Self {
+        self.param.min_tx_power = min;
+        self.param.max_tx_power = max;
+        self
+    }
+
+    /// selects the improved AGC/RSSI model of the receiver (v1.12)
+    pub fn improved_rx_model(mut self, enabled: bool) -> Self {
+        self.param.rx_model_config = enabled as u8;
+        self
+    }
+
+    /// number of advertising sets and largest advertising data, in bytes (v1.13)
+    pub fn advertising(mut self, max_adv_set_nbr: u8, max_adv_data_len: u16) -> Self {
+        self.param.max_adv_set_nbr = max_adv_set_nbr;
+        self.param.max_adv_data_len = max_adv_data_len;
+        self
+    }
+
+    /// gain of the TX and RX paths between the radio and the antenna, in 0.1 dB (v1.13)
+    pub fn path_compensation(mut self, tx: i16, rx: i16) -> Self {
+        self.param.tx_path_compens = tx;
+        self.param.rx_path_compens = rx;
+        self
+    }
+
+    /// Bluetooth core version reported by the stack (v1.15)
+    pub fn ble_core_version(mut self, version: BleCoreVersion) -> Self {
+        self.param.ble_core_version = version as u8;
+        self
+    }
+
+    /// checks the parameters and derives the memory pool sizes
+    pub fn build(self) -> Result<BleInitConfig, BleInitError> {
+        let defaults = Self::new(self.version).param;
+        let mut param = self.param;
+
+        let num_links = param.num_of_links;
+        if !(1..=MAX_LINKS).contains(&num_links) {
+            return Err(BleInitError::BadNumLinks(num_links));
+        }
+
+        // the client configuration descriptors hold a value per link
+        if let Some(links) = self.gatt_db_links {
+            if links < num_links {
+                return Err(BleInitError::GattDbLinks(links));
+            }
+        }
+
+        let max_mtu = if self.version >= BleStackVersion::V1_13 {
+            512
+        } else {
+            251
+        };
+        let att_mtu = param.att_mtu;
+        if !(BLE_DEFAULT_ATT_MTU..=max_mtu).contains(&att_mtu) {
+            return Err(BleInitError::BadAttMtu(att_mtu));
+        }
+
+        if self.version < BleStackVersion::V1_12 {
+            let changed = (
+                param.max_coc_initiator_nbr,
+                param.min_tx_power,
+                param.max_tx_power,
+                param.rx_model_config,
+            ) != (
+                defaults.max_coc_initiator_nbr,
+                defaults.min_tx_power,
+                defaults.max_tx_power,
+                defaults.rx_model_config,
+            );
+            if changed {
+                return Err(BleInitError::Unsupported(
+                    "max_coc_initiator_nbr, tx_power_range or rx_model_config",
+                    BleStackVersion::V1_12,
+                ));
+            }
+        }
+
+        let (min_tx_power, max_tx_power) = (param.min_tx_power, param.max_tx_power);
+        if min_tx_power > max_tx_power {
+            return Err(BleInitError::BadTxPowerRange(min_tx_power, max_tx_power));
+        }
+
+        if self.version < BleStackVersion::V1_13 {
+            let changed = (
+                param.max_adv_set_nbr,
+                param.max_adv_data_len,
+                param.tx_path_compens,
+                param.rx_path_compens,
+            ) != (
+                defaults.max_adv_set_nbr,
+                defaults.max_adv_data_len,
+                defaults.tx_path_compens,
+                defaults.rx_path_compens,
+            );
+            if changed {
+                return Err(BleInitError::Unsupported(
+                    "advertising or path_compensation",
+                    BleStackVersion::V1_13,
+                ));
+            }
+            if param.ll_only & BleInitOptions::EXT_ADV.bits() != 0 {
+                return Err(BleInitError::Unsupported("EXT_ADV", BleStackVersion::V1_13));
+            }
+        }
+
+        let max_adv_set_nbr = param.max_adv_set_nbr;
+        if !(1..=MAX_ADV_SETS).contains(&max_adv_set_nbr) {
+            return Err(BleInitError::BadAdvSetNumber(max_adv_set_nbr));
+        }
+
+        let max_adv_data_len = param.max_adv_data_len;
+        if !(31..=1650).contains(&max_adv_data_len) {
+            return Err(BleInitError::BadAdvDataLength(max_adv_data_len));
+        }
+
+        for compensation in [param.tx_path_compens, param.rx_path_compens] {
+            if !(-MAX_PATH_COMPENSATION..=MAX_PATH_COMPENSATION).contains(&compensation) {
+                return Err(BleInitError::BadPathCompensation(compensation));
+            }
+        }
+
+        if self.version < BleStackVersion::V1_15 {
+            let ble_core_version = param.ble_core_version;
+            if ble_core_version != defaults.ble_core_version {
+                return Err(BleInitError::Unsupported("ble_core_version", BleStackVersion::V1_15));
+            }
+        }
+
+        let prepare_write_list_size = match self.prepare_write_list_size {
+            Some(size) => size as u32,
+            None => prepare_write_list_size(att_mtu),
+        };
+        param.pr_write_list_size = to_u8(prepare_write_list_size)?;
+
+        param.mb_lock_count = match self.mblock_count {
+            Some(count) => count,
+            None => to_u8(mblocks(prepare_write_list_size, att_mtu, num_links))?,
+        };
+
+        Ok(BleInitConfig {
+            param,
+            version: self.version,
+        })
+    }
+}
+
+const fn divc(x: u32, y: u32) -> u32 {
+    (x + y - 1) / y
+}
+
+/// `BLE_PREP_WRITE_X_ATT`: prepare write requests needed to write an attribute of `att_mtu`
+/// bytes
+fn prepare_write_list_size(att_mtu: u16) -> u32 {
+    divc(att_mtu as u32, BLE_DEFAULT_ATT_MTU as u32 - 5) * 2
+}
+
+/// `BLE_MBLOCKS_CALC`: memory blocks needed by `num_links` links with an ATT MTU of `att_mtu`
+/// bytes
+fn mblocks(prepare_write_list_size: u32, att_mtu: u16, num_links: u8) -> u32 {
+    let blocks_per_packet = divc(att_mtu as u32 + 4, BLE_MEM_BLOCK_SIZE);
+    let tx = blocks_per_packet + 1;
+    let rx = (blocks_per_packet + 2) * num_links as u32 + 1;
+
+    prepare_write_list_size + (tx + rx).max(BLE_MBLOCKS_SECURE_CONNECTIONS)
+}
+
+fn to_u8(value: u32) -> Result<u8, BleInitError> {
+    u8::try_from(value).map_err(|_| BleInitError::TooManyMemoryBlocks(value))
+}
@@ -0,0 +1,164 @@
+//! System events of the STM32WB5x radio coprocessor.
+//!
+//! CPU2 reports system events on the system channel, independently of the wireless stack that
+//! is running. This module defines those events and functions to deserialize buffers into them.
+
+use byteorder::{ByteOrder, LittleEndian};
+use core::convert::TryFrom;
+
+use crate::hci::event::FirmwareKind;
+
+/// System events reported by CPU2.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum SysEvent {
+    /// CPU2 has started, either the wireless firmware or the Firmware Upgrade Service
+    C2Ready(FirmwareKind),
+
+    /// CPU2 detected an error it cannot recover from
+    ErrorNotification(SysErrorCode),
+
+    /// the BLE stack updated its NVM data in RAM, CPU1 has to write it to flash
+    BleNvmRamUpdate(NvmRamUpdate),
+
+    /// the Thread stack updated its NVM data in RAM, CPU1 has to write it to flash
+    ThreadNvmRamUpdate(NvmRamUpdate),
+
+    /// CPU2 is about to write words into flash
+    NvmStartWrite(NvmStartWrite),
+
+    /// CPU2 is done writing into flash
+    NvmEndWrite,
+
+    /// CPU2 is about to erase flash sectors
+    NvmStartErase(NvmStartErase),
+
+    /// CPU2 is done erasing flash
+    NvmEndErase,
+
+    /// CPU2 has released the synchronization bypass and is idle
+    SyncBypassIdle,
+
+    /// CPU2 runs the LLD tests and requests CPU1 to start them
+    RequestedForLldTests,
+}
+
+/// Error codes of the [`SysEvent::ErrorNotification`] event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SysErrorCode {
+    /// the BLE stack failed to start
+    BleInit,
+    ThreadLldFatalError,
+    ThreadUnknownCmd,
+    ZigbeeUnknownCmd,
+    Unknown(u8),
+}
+
+impl From<u8> for SysErrorCode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => SysErrorCode::BleInit,
+            125 => SysErrorCode::ThreadLldFatalError,
+            126 => SysErrorCode::ThreadUnknownCmd,
+            200 => SysErrorCode::ZigbeeUnknownCmd,
+            other => SysErrorCode::Unknown(other),
+        }
+    }
+}
+
+/// Parameters of the [`SysEvent::BleNvmRamUpdate`] and [`SysEvent::ThreadNvmRamUpdate`] events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct NvmRamUpdate {
+    /// start of the updated NVM data in RAM
+    pub start_address: u32,
+    /// size of the updated NVM data, in bytes
+    pub size: u32,
+}
+
+/// Parameters of the [`SysEvent::NvmStartWrite`] event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct NvmStartWrite {
+    /// number of 64-bit words CPU2 is about to write
+    pub number_of_words: u32,
+}
+
+/// Parameters of the [`SysEvent::NvmStartErase`] event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct NvmStartErase {
+    /// number of flash sectors CPU2 is about to erase
+    pub number_of_sectors: u32,
+}
+
+/// Errors that may occur when deserializing a system event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SysEventError {
+    /// The event is shorter than expected. Includes the subevent code, the actual and the
+    /// expected length.
+    BadLength(u16, usize, usize),
+
+    /// The event is not recognized. Includes the unknown subevent code.
+    UnknownEvent(u16),
+
+    /// For the [`C2Ready`](SysEvent::C2Ready) event: the kind of firmware running on CPU2 is not
+    /// recognized.
+    UnknownFirmwareKind(u8),
+}
+
+macro_rules! require_len_at_least {
+    ($code:expr, $left:expr, $right:expr) => {
+        if $left.len() < $right {
+            return Err(SysEventError::BadLength($code, $left.len(), $right));
+        }
+    };
+}
+
+impl SysEvent {
+    /// deserializes a system event from its payload, i.e. the subevent code followed by its
+    /// parameters.
+    pub fn new(buffer: &[u8]) -> Result<Self, SysEventError> {
+        require_len_at_least!(0, buffer, 2);
+
+        let code = LittleEndian::read_u16(&buffer[0..=1]);
+        let params = &buffer[2..];
+
+        match code {
+            0x9200 => {
+                require_len_at_least!(code, params, 1);
+                FirmwareKind::try_from(params[0])
+                    .map(SysEvent::C2Ready)
+                    .map_err(|_| SysEventError::UnknownFirmwareKind(params[0]))
+            }
+            0x9201 => {
+                require_len_at_least!(code, params, 1);
+                Ok(SysEvent::ErrorNotification(SysErrorCode::from(params[0])))
+            }
+            0x9202 => Ok(SysEvent::BleNvmRamUpdate(to_nvm_ram_update(code, params)?)),
+            0x9203 => Ok(SysEvent::ThreadNvmRamUpdate(to_nvm_ram_update(code, params)?)),
+            0x9204 => {
+                require_len_at_least!(code, params, 4);
+                Ok(SysEvent::NvmStartWrite(NvmStartWrite {
+                    number_of_words: LittleEndian::read_u32(&params[0..4]),
+                }))
+            }
+            0x9205 => Ok(SysEvent::NvmEndWrite),
+            0x9206 => {
+                require_len_at_least!(code, params, 4);
+                Ok(SysEvent::NvmStartErase(NvmStartErase {
+                    number_of_sectors: LittleEndian::read_u32(&params[0..4]),
+                }))
+            }
+            0x9207 => Ok(SysEvent::NvmEndErase),
+            0x9208 => Ok(SysEvent::SyncBypassIdle),
+            0x9209 => Ok(SysEvent::RequestedForLldTests),
+            _ => Err(SysEventError::UnknownEvent(code)),
+        }
+    }
+}
+
+fn to_nvm_ram_update(code: u16, params: &[u8]) -> Result<NvmRamUpdate, SysEventError> {
+    require_len_at_least!(code, params, 8);
+
+    Ok(NvmRamUpdate {
+        start_address: LittleEndian::read_u32(&params[0..4]),
+        size: LittleEndian::read_u32(&params[4..8]),
+    })
+}
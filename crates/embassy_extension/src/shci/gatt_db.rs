@@ -0,0 +1,317 @@
+//! Sizing of the GATT database of the BLE stack.
+//!
+//! CPU2 allocates the GATT database once, from the `num_attr_record`, `num_attr_serv` and
+//! `attr_value_arr_size` parameters of the BLE init command. Adding a service or a characteristic
+//! that does not fit fails later with an insufficient resources status. [`GattDb`] describes the
+//! services the application registers and computes these parameters the way ST's `app_conf.h`
+//! documents them, including the GAP and GATT services the stack adds itself.
+//!
+//! The computation is a `const fn`, so a database that does not fit the parameters of the command
+//! is a compile-time error:
+//!
+//! ```ignore
+//! const CHARACTERISTICS: &[CharacteristicDesc] = &[
+//!     CharacteristicDesc::new(Uuid::Uuid16(0x2a19), 1, CharacteristicProperty::READ),
+//!     CharacteristicDesc::new(Uuid::Uuid16(0x2a1a), 1, CharacteristicProperty::NOTIFY),
+//! ];
+//! const DB: GattDb = GattDb::new(&[ServiceDesc::new(Uuid::Uuid16(0x180f), CHARACTERISTICS)]);
+//! const DB_SIZE: GattDbSize = DB.size(2);
+//!
+//! let config = BleInitBuilder::new(BleStackVersion::V1_13)
+//!     .num_links(2)
+//!     .gatt_db_size(DB_SIZE)
+//!     .build()?;
+//! ```
+
+use crate::hci::command::gatt::{CharacteristicProperty, Uuid};
+
+/// Device name length reserved by ST's reference applications.
+const DEFAULT_DEVICE_NAME_LEN: u8 = 7;
+
+/// Attributes added by the stack: the device name, appearance and peripheral preferred connection
+/// parameters characteristics of the GAP service, the service changed characteristic of the GATT
+/// service and its client configuration descriptor.
+const BUILTIN_ATTR_RECORDS: u32 = 9;
+
+/// The GAP and GATT services.
+const BUILTIN_SERVICES: u32 = 2;
+
+/// Value length of the appearance characteristic.
+const APPEARANCE_LEN: u32 = 2;
+
+/// Value length of the peripheral preferred connection parameters characteristic.
+const PPCP_LEN: u32 = 8;
+
+/// Value length of the service changed characteristic.
+const SERVICE_CHANGED_LEN: u32 = 4;
+
+/// Value length of the server characteristic configuration and the characteristic extended
+/// properties descriptors, and of the client characteristic configuration descriptor per link.
+const DESCRIPTOR_LEN: u32 = 2;
+
+/// Storage of an attribute besides its value.
+const fn uuid_overhead(uuid: Uuid) -> u32 {
+    match uuid {
+        Uuid::Uuid16(_) => 5,
+        Uuid::Uuid128(_) => 19,
+    }
+}
+
+const fn has(properties: CharacteristicProperty, property: CharacteristicProperty) -> bool {
+    properties.bits() & property.bits() != 0
+}
+
+/// A descriptor added with `add_characteristic_descriptor`.
+#[derive(Debug, Clone, Copy)]
+pub struct DescriptorDesc {
+    /// UUID of the descriptor
+    pub uuid: Uuid,
+    /// maximum length of the descriptor value, in bytes
+    pub value_len: u16,
+}
+
+impl DescriptorDesc {
+    pub const fn new(uuid: Uuid, value_len: u16) -> Self {
+        Self { uuid, value_len }
+    }
+}
+
+/// A characteristic added with `add_characteristic`.
+#[derive(Debug, Clone, Copy)]
+pub struct CharacteristicDesc<'a> {
+    /// UUID of the characteristic
+    pub uuid: Uuid,
+    /// maximum length of the characteristic value, in bytes
+    pub value_len: u16,
+    /// properties of the characteristic, which select the descriptors added by the stack
+    pub properties: CharacteristicProperty,
+    /// descriptors added by the application
+    pub descriptors: &'a [DescriptorDesc],
+}
+
+impl<'a> CharacteristicDesc<'a> {
+    /// describes a characteristic without descriptors of the application
+    pub const fn new(uuid: Uuid, value_len: u16, properties: CharacteristicProperty) -> Self {
+        Self {
+            uuid,
+            value_len,
+            properties,
+            descriptors: &[],
+        }
+    }
+
+    /// adds the descriptors of the application
+    pub const fn with_descriptors(mut self, descriptors: &'a [DescriptorDesc]) -> Self {
+        self.descriptors = descriptors;
+        self
+    }
+
+    /// returns the attribute records of the characteristic: its declaration, its value and its
+    /// descriptors
+    pub const fn attribute_records(&self) -> u32 {
+        let mut records = 2 + self.descriptors.len() as u32;
+
+        if has(self.properties, CharacteristicProperty::BROADCAST) {
+            records += 1;
+        }
+        if has(
+            self.properties,
+            CharacteristicProperty::NOTIFY.union(CharacteristicProperty::INDICATE),
+        ) {
+            records += 1;
+        }
+        if has(self.properties, CharacteristicProperty::EXTENDED_PROPERTIES) {
+            records += 1;
+        }
+
+        records
+    }
+
+    /// returns the bytes of the attribute value array used by the characteristic with
+    /// `num_links` links
+    pub const fn attribute_value_size(&self, num_links: u8) -> u32 {
+        let mut size = self.value_len as u32 + uuid_overhead(self.uuid);
+
+        if has(self.properties, CharacteristicProperty::BROADCAST) {
+            size += DESCRIPTOR_LEN;
+        }
+        if has(
+            self.properties,
+            CharacteristicProperty::NOTIFY.union(CharacteristicProperty::INDICATE),
+        ) {
+            size += DESCRIPTOR_LEN * num_links as u32;
+        }
+        if has(self.properties, CharacteristicProperty::EXTENDED_PROPERTIES) {
+            size += DESCRIPTOR_LEN;
+        }
+
+        let mut i = 0;
+        while i < self.descriptors.len() {
+            let descriptor = &self.descriptors[i];
+            size += descriptor.value_len as u32 + uuid_overhead(descriptor.uuid);
+            i += 1;
+        }
+
+        size
+    }
+}
+
+/// A service added with `add_service`.
+#[derive(Debug, Clone, Copy)]
+pub struct ServiceDesc<'a> {
+    /// UUID of the service
+    pub uuid: Uuid,
+    /// characteristics of the service
+    pub characteristics: &'a [CharacteristicDesc<'a>],
+}
+
+impl<'a> ServiceDesc<'a> {
+    pub const fn new(uuid: Uuid, characteristics: &'a [CharacteristicDesc<'a>]) -> Self {
+        Self {
+            uuid,
+            characteristics,
+        }
+    }
+
+    /// returns the `max_attribute_records` parameter of `add_service`: the service declaration
+    /// and the records of its characteristics.
+    ///
+    /// Evaluate it in a `const` so that more than 255 records fail the build instead of
+    /// panicking.
+    pub const fn max_attribute_records(&self) -> u8 {
+        let mut records = 1;
+
+        let mut i = 0;
+        while i < self.characteristics.len() {
+            records += self.characteristics[i].attribute_records();
+            i += 1;
+        }
+
+        assert!(records <= u8::MAX as u32, "too many attribute records in a service");
+
+        records as u8
+    }
+}
+
+/// Services registered by the application.
+#[derive(Debug, Clone, Copy)]
+pub struct GattDb<'a> {
+    services: &'a [ServiceDesc<'a>],
+    device_name_len: u8,
+}
+
+impl<'a> GattDb<'a> {
+    /// describes the services of the application, with the device name length of ST's reference
+    /// applications
+    pub const fn new(services: &'a [ServiceDesc<'a>]) -> Self {
+        Self {
+            services,
+            device_name_len: DEFAULT_DEVICE_NAME_LEN,
+        }
+    }
+
+    /// sets the `device_name_char_len` passed to `gap_init`
+    pub const fn device_name_len(mut self, len: u8) -> Self {
+        self.device_name_len = len;
+        self
+    }
+
+    /// returns the services of the application
+    pub const fn services(&self) -> &'a [ServiceDesc<'a>] {
+        self.services
+    }
+
+    /// returns the smallest GATT database parameters that hold the services with `num_links`
+    /// links.
+    ///
+    /// Panics, which fails the build in a const context, if the database does not fit the
+    /// 16-bit parameters of the BLE init command.
+    pub const fn size(&self, num_links: u8) -> GattDbSize {
+        let mut records = BUILTIN_ATTR_RECORDS;
+        let mut value_size = self.device_name_len as u32
+            + uuid_overhead(Uuid::Uuid16(0))
+            + APPEARANCE_LEN
+            + uuid_overhead(Uuid::Uuid16(0))
+            + PPCP_LEN
+            + uuid_overhead(Uuid::Uuid16(0))
+            + SERVICE_CHANGED_LEN
+            + uuid_overhead(Uuid::Uuid16(0))
+            + DESCRIPTOR_LEN * num_links as u32;
+
+        let mut i = 0;
+        while i < self.services.len() {
+            let characteristics = self.services[i].characteristics;
+
+            let mut j = 0;
+            while j < characteristics.len() {
+                records += characteristics[j].attribute_records();
+                value_size += characteristics[j].attribute_value_size(num_links);
+                j += 1;
+            }
+
+            i += 1;
+        }
+
+        let services = BUILTIN_SERVICES + self.services.len() as u32;
+
+        assert!(records <= u16::MAX as u32, "too many attribute records");
+        assert!(services <= u16::MAX as u32, "too many services");
+        assert!(value_size <= u16::MAX as u32, "attribute values too large");
+
+        GattDbSize {
+            num_attr_record: records as u16,
+            num_attr_serv: services as u16,
+            attr_value_arr_size: value_size as u16,
+            num_links,
+        }
+    }
+}
+
+/// GATT database parameters of the BLE init command, computed by [`GattDb::size`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct GattDbSize {
+    /// attributes, excluding the service declarations
+    pub num_attr_record: u16,
+    /// services
+    pub num_attr_serv: u16,
+    /// size of the attribute value array, in bytes
+    pub attr_value_arr_size: u16,
+    /// links the client configuration descriptors were sized for
+    pub num_links: u8,
+}
+
+impl GattDbSize {
+    /// returns `true` if a database of this size fits in the given parameters
+    pub const fn fits(
+        &self,
+        num_attr_record: u16,
+        num_attr_serv: u16,
+        attr_value_arr_size: u16,
+    ) -> bool {
+        self.num_attr_record <= num_attr_record
+            && self.num_attr_serv <= num_attr_serv
+            && self.attr_value_arr_size <= attr_value_arr_size
+    }
+
+    /// panics, which fails the build in a const context, if a database of this size does not
+    /// fit in the given parameters
+    pub const fn assert_fits(
+        &self,
+        num_attr_record: u16,
+        num_attr_serv: u16,
+        attr_value_arr_size: u16,
+    ) {
+        assert!(
+            self.num_attr_record <= num_attr_record,
+            "num_attr_record is too small for the GATT database"
+        );
+        assert!(
+            self.num_attr_serv <= num_attr_serv,
+            "num_attr_serv is too small for the GATT database"
+        );
+        assert!(
+            self.attr_value_arr_size <= attr_value_arr_size,
+            "attr_value_arr_size is too small for the GATT database"
+        );
+    }
+}
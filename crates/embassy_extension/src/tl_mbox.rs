@@ -1,6 +1,7 @@
 use self::{
     cmd::{AclDataPacket, CmdPacket},
-    evt::{CcEvt, EvtBox},
+    evt::EvtBox,
+    shci::ShciResponse,
 };
 use crate::{ipcc::Ipcc, unsafe_linked_list::LinkedListNode};
 use bit_field::BitField;
@@ -11,10 +12,14 @@ pub mod channels;
 pub mod cmd;
 pub mod consts;
 pub mod evt;
+pub mod layout;
 pub mod lhci;
 pub mod mm;
 pub mod shci;
+pub mod snapshot;
+pub mod stats;
 pub mod sys;
+pub mod thread;
 
 #[derive(Debug, Copy, Clone)]
 #[repr(C, packed)]
@@ -253,6 +258,7 @@ pub static mut SYS_CMD_BUF: MaybeUninit<CmdPacket> = MaybeUninit::uninit();
  * the system may hang if the queue is full with asynchronous events and the HCI layer is still waiting
  * for a CC/CS event, In that case, the notification TL_BLE_HCI_ToNot() is called to indicate
  * to the application a HCI command did not receive its command event within 30s (Default HCI Timeout).
+ * Here, `Ble::perform_command` times out instead, see `ble::DEFAULT_COMMAND_TIMEOUT`.
  */
 const CFG_TLBLE_EVT_QUEUE_LENGTH: usize = 5;
 const CFG_TLBLE_MOST_EVENT_PAYLOAD_SIZE: usize = 255;
@@ -284,22 +290,63 @@ static mut BLE_CMD_BUFFER: MaybeUninit<CmdPacket> = MaybeUninit::uninit();
 static mut HCI_ACL_DATA_BUFFER: MaybeUninit<[u8; TL_PACKET_HEADER_SIZE + 5 + 251]> =
     MaybeUninit::uninit();
 
+#[link_section = "MB_MEM2"]
+static mut THREAD_OT_CMD_BUFFER: MaybeUninit<CmdPacket> = MaybeUninit::uninit();
+
+#[link_section = "MB_MEM2"]
+static mut THREAD_NOTIF_RSP_EVT_BUFFER: MaybeUninit<
+    [u8; TL_PACKET_HEADER_SIZE + TL_EVT_HEADER_SIZE + 255],
+> = MaybeUninit::uninit();
+
+#[link_section = "MB_MEM2"]
+static mut THREAD_CLI_CMD_BUFFER: MaybeUninit<CmdPacket> = MaybeUninit::uninit();
+
+/// Size of the BLE stack NVM data (bonding and security database), in 32-bit words.
+pub const BLE_NVM_SRAM_SIZE: usize = 507;
+
+/// BLE stack NVM data, only used when CPU2 is configured to keep it in SRAM, see [`crate::nvm`]
+#[link_section = "MB_MEM2"]
+pub(crate) static mut BLE_NVM_SRAM: MaybeUninit<[u32; BLE_NVM_SRAM_SIZE]> = MaybeUninit::uninit();
+
 pub type HeaplessEvtQueue = heapless::spsc::Queue<EvtBox, 32>;
 
+/// moves an event received from CPU2 into the internal event queue.
+///
+/// If the queue is full the event is dropped, which gives its buffer back to CPU2.
+fn enqueue_evt(queue: &mut HeaplessEvtQueue, event: EvtBox) {
+    if queue.enqueue(event).is_err() {
+        defmt::warn!("mailbox event queue is full, dropping event");
+        stats::COUNTERS.evt_queue_overflows.increment();
+    }
+
+    stats::COUNTERS
+        .evt_queue_high_water
+        .record_max(queue.len() as u32);
+}
+
 pub struct TlMbox {
     sys: sys::Sys,
     ble: ble::Ble,
+    /// only set once the Thread transport layer is initialized, see [`TlMbox::init_thread`]
+    thread: Option<thread::Thread>,
     _mm: mm::MemoryManager,
 
     /// current event that is produced during IPCC IRQ handler execution
-    /// on SYS channel
+    /// on BLE channel
     evt_queue: HeaplessEvtQueue,
-    /// last received Command Complete event
-    last_cc_event: Option<evt::CcEvt>,
+    /// current event that is produced during IPCC IRQ handler execution
+    /// on SYS channel
+    sys_evt_queue: HeaplessEvtQueue,
+    /// last response received on the system command channel
+    last_sys_rsp: Option<ShciResponse>,
 }
 
 impl TlMbox {
     pub fn init(ipcc: &mut Ipcc) -> Self {
+        if let Err(e) = layout::check() {
+            defmt::panic!("mailbox memory is not accessible to CPU2: {}", e);
+        }
+
         unsafe {
             TL_REF_TABLE.as_mut_ptr().write_volatile(RefTable {
                 device_info_table: TL_DEVICE_INFO_TABLE.as_mut_ptr(),
@@ -335,13 +382,23 @@ impl TlMbox {
         let mm = mm::MemoryManager::new();
 
         let evt_queue = heapless::spsc::Queue::new();
+        let sys_evt_queue = heapless::spsc::Queue::new();
 
         Self {
             sys,
             ble,
+            thread: None,
             _mm: mm,
             evt_queue,
-            last_cc_event: None,
+            sys_evt_queue,
+            last_sys_rsp: None,
+        }
+    }
+
+    /// registers the Thread buffers, has to be done before the Thread stack is started on CPU2
+    pub fn init_thread(&mut self, ipcc: &mut Ipcc) {
+        if self.thread.is_none() {
+            self.thread = Some(thread::Thread::new(ipcc));
         }
     }
 
@@ -360,25 +417,31 @@ impl TlMbox {
     pub fn interrupt_ipcc_rx_handler(&mut self, ipcc: &mut Ipcc) {
         if ipcc.is_rx_pending(channels::cpu2::IPCC_SYSTEM_EVENT_CHANNEL) {
             defmt::debug!("rx interrupt sys evt");
-            self.sys.evt_handler(ipcc, &mut self.evt_queue);
+            self.sys.evt_handler(ipcc, &mut self.sys_evt_queue);
         } else if ipcc.is_rx_pending(channels::cpu2::IPCC_THREAD_NOTIFICATION_ACK_CHANNEL) {
-            todo!()
+            if let Some(thread) = &self.thread {
+                thread.notification_handler(ipcc);
+            }
         } else if ipcc.is_rx_pending(channels::cpu2::IPCC_BLE_EVENT_CHANNEL) {
             defmt::debug!("rx interrupt ble evt");
             self.ble.evt_handler(ipcc, &mut self.evt_queue);
         } else if ipcc.is_rx_pending(channels::cpu2::IPCC_TRACES_CHANNEL) {
             todo!()
         } else if ipcc.is_rx_pending(channels::cpu2::IPCC_THREAD_CLI_NOTIFICATION_ACK_CHANNEL) {
-            todo!()
+            if let Some(thread) = &self.thread {
+                thread.cli_notification_handler(ipcc);
+            }
         }
     }
 
     pub fn interrupt_ipcc_tx_handler(&mut self, ipcc: &mut Ipcc) {
         if ipcc.is_tx_pending(channels::cpu1::IPCC_SYSTEM_CMD_RSP_CHANNEL) {
             defmt::debug!("tx interrupt sys cmd rsp");
-            self.last_cc_event = Some(self.sys.cmd_evt_handler(ipcc));
+            self.last_sys_rsp = Some(self.sys.cmd_evt_handler(ipcc));
         } else if ipcc.is_tx_pending(channels::cpu1::IPCC_THREAD_OT_CMD_RSP_CHANNEL) {
-            todo!()
+            // OpenThread commands are never sent, see [`thread::Thread`]
+            defmt::warn!("unexpected tx interrupt thread ot cmd rsp");
+            ipcc.c1_set_tx_channel(channels::cpu1::IPCC_THREAD_OT_CMD_RSP_CHANNEL, false);
         } else if ipcc.is_tx_pending(channels::cpu1::IPCC_MM_RELEASE_BUFFER_CHANNEL) {
             defmt::debug!("tx interrupt mm");
             mm::free_buf_handler(ipcc);
@@ -395,11 +458,25 @@ impl TlMbox {
         self.evt_queue.dequeue()
     }
 
-    /// retrieves last Command Complete event and removes it from mailbox
-    pub fn pop_last_cc_evt(&mut self) -> Option<CcEvt> {
-        self.last_cc_event.map(|evt| {
-            self.last_cc_event = None; // remove event
-            evt
-        })
+    /// picks single system [`EvtBox`] from internal system event queue.
+    ///
+    /// Internal system event queue is populated in IPCC_RX_IRQ handler
+    pub fn dequeue_sys_event(&mut self) -> Option<EvtBox> {
+        self.sys_evt_queue.dequeue()
+    }
+
+    /// returns a snapshot of the mailbox counters
+    pub fn stats(&self) -> stats::MboxStats {
+        stats::COUNTERS.snapshot()
+    }
+
+    /// resets all mailbox counters and high-water marks
+    pub fn reset_stats(&mut self) {
+        stats::COUNTERS.reset();
+    }
+
+    /// retrieves the last SHCI command response and removes it from mailbox
+    pub fn pop_sys_rsp(&mut self) -> Option<ShciResponse> {
+        self.last_sys_rsp.take()
     }
 }
@@ -1,8 +1,10 @@
 use super::{
     channels,
-    cmd::{CmdPacket, CmdSerial},
+    cmd::{AclDataSerial, CmdPacket, CmdSerial},
     consts::TlPacketType,
+    enqueue_evt,
     evt::EvtBox,
+    stats::COUNTERS,
     BleTable, HeaplessEvtQueue, BLE_CMD_BUFFER, CS_BUFFER, EVT_QUEUE, HCI_ACL_DATA_BUFFER,
     TL_BLE_TABLE, TL_REF_TABLE,
 };
@@ -42,22 +44,27 @@ impl Ble {
                 let event = node_ptr.cast();
                 let event = EvtBox::new(event);
 
-                queue.enqueue(event).unwrap();
+                COUNTERS.ble_events.increment();
+                enqueue_evt(queue, event);
             }
         }
 
         ipcc.c1_clear_flag_channel(channels::cpu2::IPCC_BLE_EVENT_CHANNEL);
     }
 
+    /// CPU2 released the ACL data buffer, the sender waiting in
+    /// [`Ble::send_acl_data`](crate::ble::Ble::send_acl_data) is woken by the TX interrupt
     pub(super) fn acl_data_handler(&self, ipcc: &mut Ipcc) {
         ipcc.c1_set_tx_channel(channels::cpu1::IPCC_HCI_ACL_DATA_CHANNEL, false);
 
-        // TODO: ACL data ack to the user
+        COUNTERS.acl_packets_acked.increment();
     }
 }
 
 pub fn ble_send_cmd(ipcc: &mut Ipcc, buf: &[u8]) {
     defmt::debug!("ble send {:#04x}", buf);
+    COUNTERS.ble_commands.increment();
+
     unsafe {
         let pcmd_buffer: *mut CmdPacket = (*TL_REF_TABLE.assume_init().ble_table).pcmd_buffer;
         let pcmd_serial: *mut CmdSerial = &mut (*pcmd_buffer).cmdserial;
@@ -72,12 +79,19 @@ pub fn ble_send_cmd(ipcc: &mut Ipcc, buf: &[u8]) {
     ipcc.c1_set_flag_channel(channels::cpu1::IPCC_BLE_CMD_CHANNEL);
 }
 
-#[allow(dead_code)] // Not used currently but reserved
-pub(super) fn ble_send_acl_data(ipcc: &mut Ipcc) {
+/// sends an ACL data packet, `buf` starts with the packet type, the handle and the length.
+///
+/// CPU2 releases the buffer with a TX interrupt of the ACL data channel.
+pub fn ble_send_acl_data(ipcc: &mut Ipcc, buf: &[u8]) {
     let mut cmd_packet =
         unsafe { &mut *(*TL_REF_TABLE.assume_init().ble_table).phci_acl_data_buffer };
 
+    unsafe {
+        let acl_serial: *mut AclDataSerial = &mut cmd_packet.acl_data_serial;
+        core::ptr::copy(buf.as_ptr(), acl_serial.cast(), buf.len());
+    }
     cmd_packet.acl_data_serial.ty = TlPacketType::AclData as u8;
+    COUNTERS.acl_packets_sent.increment();
 
     ipcc.c1_set_flag_channel(channels::cpu1::IPCC_HCI_ACL_DATA_CHANNEL);
     ipcc.c1_set_tx_channel(channels::cpu1::IPCC_HCI_ACL_DATA_CHANNEL, true);
@@ -1,5 +1,10 @@
 use core::convert::TryFrom;
 
+/// Event code of the command complete events, of CPU2 and of the local commands.
+pub const TL_BLEEVT_CC_OPCODE: u8 = 0x0e;
+/// Event code of the command status events.
+pub const TL_BLEEVT_CS_OPCODE: u8 = 0x0f;
+
 #[derive(Debug, defmt::Format)]
 #[repr(C)]
 pub enum TlPacketType {
@@ -0,0 +1,150 @@
+//! Shared memory layout checks.
+//!
+//! CPU2 reads the mailbox tables straight out of SRAM2, so their binary layout and their location
+//! have to match what ST's wireless stack expects. The sizes of the tables are checked at compile
+//! time against ST's C definitions, their location is checked by [`check`] at runtime (and by the
+//! assertions in the `tl_mbox.x` linker fragment generated by this crate's build script).
+
+use core::mem::{align_of, size_of};
+
+use super::{
+    cmd::{AclDataPacket, AclDataSerial, Cmd, CmdPacket, CmdSerial},
+    evt::{AsynchEvt, CcEvt, CsEvt, Evt, EvtPacket, EvtSerial},
+    lhci::{LhciC1DeviceInformationCcrp, LhciC1ReadRegisterCcrp},
+    shci::{
+        ShciBleInitCmdPacket, ShciBleInitCmdParam, ShciConfigParam, ShciExtpaConfigParam,
+        ShciHeader,
+    },
+    DeviceInfoTable, RssInfoTable, SafeBootInfoTable, WirelessFwInfoTable,
+};
+
+// SRAM2 map, generated by the build script from the map of the linker fragment
+include!(concat!(env!("OUT_DIR"), "/sram2.rs"));
+
+macro_rules! assert_layout {
+    ($ty:ty, size = $size:expr, align = $align:expr) => {
+        const _: () = assert!(size_of::<$ty>() == $size, concat!("bad size of ", stringify!($ty)));
+        const _: () =
+            assert!(align_of::<$ty>() == $align, concat!("bad alignment of ", stringify!($ty)));
+    };
+}
+
+// device information table
+assert_layout!(SafeBootInfoTable, size = 4, align = 1);
+assert_layout!(RssInfoTable, size = 12, align = 1);
+assert_layout!(WirelessFwInfoTable, size = 16, align = 1);
+assert_layout!(DeviceInfoTable, size = 32, align = 4);
+
+// events
+assert_layout!(CsEvt, size = 4, align = 1);
+assert_layout!(CcEvt, size = 4, align = 1);
+assert_layout!(AsynchEvt, size = 3, align = 1);
+assert_layout!(Evt, size = 3, align = 1);
+assert_layout!(EvtSerial, size = 4, align = 1);
+
+// commands
+assert_layout!(Cmd, size = 258, align = 1);
+assert_layout!(CmdSerial, size = 259, align = 1);
+assert_layout!(AclDataSerial, size = 6, align = 1);
+
+// system commands
+assert_layout!(ShciHeader, size = 12, align = 1);
+assert_layout!(ShciBleInitCmdParam, size = 45, align = 1);
+assert_layout!(ShciBleInitCmdPacket, size = 57, align = 1);
+assert_layout!(ShciConfigParam, size = 16, align = 1);
+assert_layout!(ShciExtpaConfigParam, size = 8, align = 1);
+
+// local commands
+assert_layout!(LhciC1DeviceInformationCcrp, size = 63, align = 1);
+assert_layout!(LhciC1ReadRegisterCcrp, size = 5, align = 1);
+
+/// Tables holding pointers only match ST's layout on the 32-bit CPU1 core.
+#[cfg(target_pointer_width = "32")]
+mod pointer_tables {
+    use super::super::{
+        BleLldTable, BleTable, LldTestsTable, Mac802_15_4Table, MemManagerTable, RefTable,
+        SysTable, ThreadTable, TracesTable, ZigbeeTable,
+    };
+    use super::*;
+    use crate::unsafe_linked_list::LinkedListNode;
+
+    assert_layout!(LinkedListNode, size = 8, align = 4);
+    assert_layout!(EvtPacket, size = 12, align = 1);
+    assert_layout!(CmdPacket, size = 267, align = 1);
+    assert_layout!(AclDataPacket, size = 14, align = 1);
+
+    assert_layout!(BleTable, size = 16, align = 4);
+    assert_layout!(ThreadTable, size = 12, align = 4);
+    assert_layout!(LldTestsTable, size = 8, align = 4);
+    assert_layout!(BleLldTable, size = 8, align = 4);
+    assert_layout!(ZigbeeTable, size = 12, align = 4);
+    assert_layout!(SysTable, size = 8, align = 4);
+    assert_layout!(MemManagerTable, size = 28, align = 4);
+    assert_layout!(TracesTable, size = 4, align = 4);
+    assert_layout!(Mac802_15_4Table, size = 12, align = 4);
+    assert_layout!(RefTable, size = 28, align = 4);
+}
+
+/// A mailbox table that CPU2 would not be able to find or to access.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum LayoutError {
+    /// The reference table is not at the start of SRAM2a. Includes its actual address.
+    RefTableMisplaced(usize),
+
+    /// A table or a buffer lies outside of SRAM2a/SRAM2b. Includes its name and address.
+    NotInSharedRam(&'static str, usize),
+}
+
+/// returns `true` if the `size` bytes at `address` lie in SRAM2a/SRAM2b
+pub(crate) fn in_shared_ram(address: usize, size: usize) -> bool {
+    address >= SRAM2A_BASE && address + size <= SRAM2B_BASE + SRAM2B_SIZE
+}
+
+macro_rules! check_shared {
+    ($($name:ident),+ $(,)?) => {
+        $(
+            let address = unsafe { super::$name.as_ptr() } as usize;
+            if !in_shared_ram(address, unsafe { size_of_val(&super::$name) }) {
+                return Err(LayoutError::NotInSharedRam(stringify!($name), address));
+            }
+        )+
+    };
+}
+
+/// Checks that the reference table is where CPU2 looks for it, and that every table and buffer
+/// it points to lies in CPU2-accessible RAM.
+pub fn check() -> Result<(), LayoutError> {
+    use core::mem::size_of_val;
+
+    let ref_table = unsafe { super::TL_REF_TABLE.as_ptr() } as usize;
+    if ref_table != SRAM2A_BASE {
+        return Err(LayoutError::RefTableMisplaced(ref_table));
+    }
+
+    check_shared!(
+        TL_DEVICE_INFO_TABLE,
+        TL_BLE_TABLE,
+        TL_THREAD_TABLE,
+        TL_SYS_TABLE,
+        TL_MEM_MANAGER_TABLE,
+        TL_TRACES_TABLE,
+        TL_MAC_802_15_4_TABLE,
+        FREE_BUF_QUEUE,
+        TRACES_EVT_QUEUE,
+        CS_BUFFER,
+        EVT_QUEUE,
+        SYSTEM_EVT_QUEUE,
+        SYS_CMD_BUF,
+        EVT_POOL,
+        SYS_SPARE_EVT_BUF,
+        BLE_SPARE_EVT_BUF,
+        BLE_CMD_BUFFER,
+        HCI_ACL_DATA_BUFFER,
+        BLE_NVM_SRAM,
+        THREAD_OT_CMD_BUFFER,
+        THREAD_NOTIF_RSP_EVT_BUFFER,
+        THREAD_CLI_CMD_BUFFER,
+    );
+
+    Ok(())
+}
@@ -1,19 +1,45 @@
+//! Local HCI (LHCI) commands.
+//!
+//! Tools such as STM32CubeMonitor-RF send local commands next to the BLE and system commands.
+//! They are packets of kind [`TlPacketType::LocCmd`] that CPU2 does not know: CPU1 answers them
+//! itself with a command complete event of kind [`TlPacketType::LocRsp`]. [`process`] recognizes
+//! them in the command stream of the tool and writes the response:
+//!
+//! ```ignore
+//! if lhci::is_local_command(&packet) {
+//!     let len = unsafe { lhci::process(&packet, &mut response) }?;
+//!     uart.write(&response[..len]).await?;
+//! } else {
+//!     // forward the BLE or system command to CPU2
+//! }
+//! ```
+
 use super::{
     cmd::CmdPacket,
-    consts::TlPacketType,
+    consts::{TlPacketType, TL_BLEEVT_CC_OPCODE},
     evt::{CcEvt, EvtPacket, EvtSerial},
     DeviceInfoTable, RssInfoTable, SafeBootInfoTable, WirelessFwInfoTable, TL_EVT_HEADER_SIZE,
     TL_REF_TABLE,
 };
 
-const TL_BLEEVT_CC_OPCODE: u8 = 0x0e;
-#[allow(dead_code)] // Not used currently but reserved
-const TL_BLEEVT_CS_OPCODE: u8 = 0x0f;
+pub const LHCI_OPCODE_C1_WRITE_REG: u16 = 0xfd60;
+pub const LHCI_OPCODE_C1_READ_REG: u16 = 0xfd61;
+pub const LHCI_OPCODE_C1_DEVICE_INF: u16 = 0xfd62;
+
+/// Status of a response, as the HCI error codes.
+const LHCI_STATUS_SUCCESS: u8 = 0x00;
+const LHCI_STATUS_UNKNOWN_COMMAND: u8 = 0x01;
+const LHCI_STATUS_INVALID_PARAMETERS: u8 = 0x12;
 
-const LHCI_OPCODE_C1_DEVICE_INF: u16 = 0xfd62;
+/// Kind, opcode and payload length of a command packet.
+const LHCI_CMD_HEADER_SIZE: usize = 4;
+
+/// Kind, event code, payload length, number of commands and opcode of a response packet.
+const LHCI_RSP_HEADER_SIZE: usize = 6;
 
 const PACKAGE_DATA_PTR: *const u8 = 0x1FFF_7500 as _;
 const UID64_PTR: *const u32 = 0x1FFF_7580 as _;
+const DBGMCU_IDCODE_PTR: *const u32 = 0xE004_2000 as _;
 
 #[derive(Debug, Copy, Clone)]
 #[repr(C, packed)]
@@ -45,10 +71,9 @@ impl Default for LhciC1DeviceInformationCcrp {
             wireless_fw_info_table,
         } = unsafe { &*(*TL_REF_TABLE.as_ptr()).device_info_table }.clone();
 
-        // TODO: figure out how to get this information
-        // let dbgmcu = unsafe { embassy_stm32::Peripherals::steal() }.DBGMCU;
-        // let rev_id = dbgmcu.idcode.read().rev_id().bits();
-        // let dev_code_id = dbgmcu.idcode.read().dev_id().bits();
+        let idcode = unsafe { core::ptr::read_volatile(DBGMCU_IDCODE_PTR) };
+        let rev_id = (idcode >> 16) as u16;
+        let dev_code_id = (idcode & 0x0FFF) as u16;
 
         let device_id = stm32_device_signature::device_id();
         let uid96_0 = (device_id[3] as u32) << 24
@@ -70,9 +95,9 @@ impl Default for LhciC1DeviceInformationCcrp {
         let device_type_id = (unsafe { *UID64_PTR.offset(1) } & 0x000000FF) as u8;
 
         LhciC1DeviceInformationCcrp {
-            status: 0,
-            rev_id: 0,
-            dev_code_id: 0,
+            status: LHCI_STATUS_SUCCESS,
+            rev_id,
+            dev_code_id,
             package_type,
             device_type_id,
             st_company_id,
@@ -119,3 +144,237 @@ impl LhciC1DeviceInformationCcrp {
         }
     }
 }
+
+/// Response to the C1 read register command.
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct LhciC1ReadRegisterCcrp {
+    pub status: u8,
+    pub value: u32,
+}
+
+/// Width of a register access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum BusWidth {
+    Byte,
+    HalfWord,
+    Word,
+}
+
+impl TryFrom<u8> for BusWidth {
+    type Error = LhciError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(BusWidth::Byte),
+            2 => Ok(BusWidth::HalfWord),
+            4 => Ok(BusWidth::Word),
+            _ => Err(LhciError::InvalidParameters),
+        }
+    }
+}
+
+/// Errors of the local commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum LhciError {
+    /// The packet is not a local command.
+    NotLocal,
+    /// The packet is shorter than its header or than its payload length.
+    Truncated,
+    /// The opcode is not a local command. Includes the opcode.
+    UnknownOpcode(u16),
+    /// The payload does not match the opcode.
+    InvalidParameters,
+    /// The response buffer is too small.
+    BufferTooSmall,
+}
+
+/// Local commands handled by CPU1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum LhciCommand {
+    /// writes the bits of `value` selected by `mask` to the register at `address`
+    WriteRegister {
+        bus_width: BusWidth,
+        mask: u32,
+        address: u32,
+        value: u32,
+    },
+    /// reads the register at `address`
+    ReadRegister { bus_width: BusWidth, address: u32 },
+    /// reads the device information, see [`LhciC1DeviceInformationCcrp`]
+    DeviceInformation,
+}
+
+impl LhciCommand {
+    /// parses a local command packet: its kind, opcode, payload length and payload
+    pub fn parse(packet: &[u8]) -> Result<Self, LhciError> {
+        if !is_local_command(packet) {
+            return Err(LhciError::NotLocal);
+        }
+
+        let (opcode, payload) = command_parts(packet)?;
+        let word = |offset: usize| {
+            payload
+                .get(offset..offset + 4)
+                .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .ok_or(LhciError::InvalidParameters)
+        };
+
+        match opcode {
+            LHCI_OPCODE_C1_WRITE_REG => Ok(LhciCommand::WriteRegister {
+                bus_width: BusWidth::try_from(*payload.first().unwrap_or(&0))?,
+                mask: word(1)?,
+                address: word(5)?,
+                value: word(9)?,
+            }),
+            LHCI_OPCODE_C1_READ_REG => Ok(LhciCommand::ReadRegister {
+                bus_width: BusWidth::try_from(*payload.first().unwrap_or(&0))?,
+                address: word(1)?,
+            }),
+            LHCI_OPCODE_C1_DEVICE_INF => Ok(LhciCommand::DeviceInformation),
+            opcode => Err(LhciError::UnknownOpcode(opcode)),
+        }
+    }
+
+    /// returns the opcode of the command
+    pub fn opcode(&self) -> u16 {
+        match self {
+            LhciCommand::WriteRegister { .. } => LHCI_OPCODE_C1_WRITE_REG,
+            LhciCommand::ReadRegister { .. } => LHCI_OPCODE_C1_READ_REG,
+            LhciCommand::DeviceInformation => LHCI_OPCODE_C1_DEVICE_INF,
+        }
+    }
+
+    /// runs the command and returns its response.
+    ///
+    /// # Safety
+    ///
+    /// The register commands access any address given by the tool, which has to be a readable
+    /// and, for [`LhciCommand::WriteRegister`], writable register aligned to the bus width.
+    pub unsafe fn execute(&self) -> LhciResponse {
+        match *self {
+            LhciCommand::WriteRegister {
+                bus_width,
+                mask,
+                address,
+                value,
+            } => {
+                // an interrupt must not modify the register between the read and the write
+                critical_section::with(|_| {
+                    let current = read_register(bus_width, address);
+                    write_register(bus_width, address, (current & !mask) | (value & mask));
+                });
+
+                LhciResponse::WriteRegister
+            }
+            LhciCommand::ReadRegister { bus_width, address } => {
+                LhciResponse::ReadRegister(LhciC1ReadRegisterCcrp {
+                    status: LHCI_STATUS_SUCCESS,
+                    value: read_register(bus_width, address),
+                })
+            }
+            LhciCommand::DeviceInformation => {
+                LhciResponse::DeviceInformation(LhciC1DeviceInformationCcrp::new())
+            }
+        }
+    }
+}
+
+/// Responses to the local commands.
+#[derive(Debug, Clone, Copy)]
+pub enum LhciResponse {
+    WriteRegister,
+    ReadRegister(LhciC1ReadRegisterCcrp),
+    DeviceInformation(LhciC1DeviceInformationCcrp),
+}
+
+impl LhciResponse {
+    /// writes the response packet to `opcode` into `buf` and returns its length
+    pub fn write(&self, opcode: u16, buf: &mut [u8]) -> Result<usize, LhciError> {
+        match self {
+            LhciResponse::WriteRegister => write_ccrp(opcode, &[LHCI_STATUS_SUCCESS], buf),
+            LhciResponse::ReadRegister(ccrp) => write_ccrp(opcode, struct_bytes(ccrp), buf),
+            LhciResponse::DeviceInformation(ccrp) => write_ccrp(opcode, struct_bytes(ccrp), buf),
+        }
+    }
+}
+
+/// returns `true` if `packet` is a local command that CPU1 answers itself
+pub fn is_local_command(packet: &[u8]) -> bool {
+    packet.first() == Some(&(TlPacketType::LocCmd as u8))
+}
+
+/// answers the local command `packet`, writes the response packet into `response` and returns
+/// its length.
+///
+/// Unknown opcodes and malformed parameters are answered with an error status, as CPU2 does for
+/// the BLE commands.
+///
+/// # Safety
+///
+/// See [`LhciCommand::execute`].
+pub unsafe fn process(packet: &[u8], response: &mut [u8]) -> Result<usize, LhciError> {
+    match LhciCommand::parse(packet) {
+        Ok(command) => command.execute().write(command.opcode(), response),
+        Err(LhciError::UnknownOpcode(opcode)) => {
+            write_ccrp(opcode, &[LHCI_STATUS_UNKNOWN_COMMAND], response)
+        }
+        Err(LhciError::InvalidParameters) => {
+            let (opcode, _) = command_parts(packet)?;
+            write_ccrp(opcode, &[LHCI_STATUS_INVALID_PARAMETERS], response)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// splits a command packet into its opcode and payload
+fn command_parts(packet: &[u8]) -> Result<(u16, &[u8]), LhciError> {
+    if packet.len() < LHCI_CMD_HEADER_SIZE {
+        return Err(LhciError::Truncated);
+    }
+
+    let opcode = u16::from_le_bytes([packet[1], packet[2]]);
+    let payload = packet[LHCI_CMD_HEADER_SIZE..]
+        .get(..packet[3] as usize)
+        .ok_or(LhciError::Truncated)?;
+
+    Ok((opcode, payload))
+}
+
+/// writes a command complete event of kind [`TlPacketType::LocRsp`]
+fn write_ccrp(opcode: u16, payload: &[u8], buf: &mut [u8]) -> Result<usize, LhciError> {
+    let len = LHCI_RSP_HEADER_SIZE + payload.len();
+    if buf.len() < len {
+        return Err(LhciError::BufferTooSmall);
+    }
+
+    buf[0] = TlPacketType::LocRsp as u8;
+    buf[1] = TL_BLEEVT_CC_OPCODE;
+    buf[2] = (len - TL_EVT_HEADER_SIZE) as u8;
+    buf[3] = 1;
+    buf[4..6].copy_from_slice(&opcode.to_le_bytes());
+    buf[LHCI_RSP_HEADER_SIZE..len].copy_from_slice(payload);
+
+    Ok(len)
+}
+
+/// returns the bytes of a `#[repr(C, packed)]` response
+fn struct_bytes<T>(ccrp: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts((ccrp as *const T).cast(), core::mem::size_of::<T>()) }
+}
+
+unsafe fn read_register(bus_width: BusWidth, address: u32) -> u32 {
+    match bus_width {
+        BusWidth::Byte => core::ptr::read_volatile(address as *const u8) as u32,
+        BusWidth::HalfWord => core::ptr::read_volatile(address as *const u16) as u32,
+        BusWidth::Word => core::ptr::read_volatile(address as *const u32),
+    }
+}
+
+unsafe fn write_register(bus_width: BusWidth, address: u32, value: u32) {
+    match bus_width {
+        BusWidth::Byte => core::ptr::write_volatile(address as *mut u8, value as u8),
+        BusWidth::HalfWord => core::ptr::write_volatile(address as *mut u16, value as u16),
+        BusWidth::Word => core::ptr::write_volatile(address as *mut u32, value),
+    }
+}
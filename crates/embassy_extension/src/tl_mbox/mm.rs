@@ -8,8 +8,9 @@ use crate::{
 };
 
 use super::{
-    channels, evt::EvtPacket, MemManagerTable, BLE_SPARE_EVT_BUF, EVT_POOL, FREE_BUF_QUEUE,
-    LOCAL_FREE_BUF_QUEUE, POOL_SIZE, SYS_SPARE_EVT_BUF, TL_MEM_MANAGER_TABLE, TL_REF_TABLE,
+    channels, evt::EvtPacket, stats::COUNTERS, MemManagerTable, BLE_SPARE_EVT_BUF, EVT_POOL,
+    FREE_BUF_QUEUE, LOCAL_FREE_BUF_QUEUE, POOL_SIZE, SYS_SPARE_EVT_BUF, TL_MEM_MANAGER_TABLE,
+    TL_REF_TABLE,
 };
 
 pub(super) struct MemoryManager;
@@ -40,12 +41,14 @@ pub fn evt_drop(evt: *mut EvtPacket, ipcc: &mut Ipcc) {
         let list_node = evt.cast();
 
         LST_insert_tail(LOCAL_FREE_BUF_QUEUE.as_mut_ptr(), list_node);
+        COUNTERS.buffers_released.increment();
 
         let channel_is_busy =
             ipcc.c1_is_active_flag(channels::cpu1::IPCC_MM_RELEASE_BUFFER_CHANNEL);
 
         // postpone event buffer freeing to IPCC interrupt handler
         if channel_is_busy {
+            COUNTERS.release_deferrals.increment();
             ipcc.c1_set_tx_channel(channels::cpu1::IPCC_MM_RELEASE_BUFFER_CHANNEL, true);
         } else {
             send_free_buf();
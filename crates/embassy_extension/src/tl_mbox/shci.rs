@@ -1,12 +1,159 @@
 use crate::ipcc::Ipcc;
 
 use super::{
-    cmd::CmdPacket, consts::TlPacketType, sys, TL_CS_EVT_SIZE, TL_EVT_HEADER_SIZE,
-    TL_PACKET_HEADER_SIZE, TL_SYS_TABLE,
+    consts::{TlPacketType, TL_BLEEVT_CC_OPCODE},
+    sys, TL_CS_EVT_SIZE, TL_EVT_HEADER_SIZE, TL_PACKET_HEADER_SIZE, TL_SYS_TABLE,
 };
 
-const SCHI_OPCODE_BLE_INIT: u16 = 0xfc66;
+/// Number of response payload bytes kept in a [`ShciResponse`], status byte excluded.
+pub const SHCI_RSP_PAYLOAD_LEN: usize = 16;
 
+/// Packet type, event code, length, `num_cmd`, opcode and status of a written [`ShciResponse`].
+const SHCI_RSP_HEADER_SIZE: usize = 7;
+
+/// SHCI command opcodes (OGF 0x3F, vendor specific).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u16)]
+pub enum ShciOpcode {
+    FusGetState = 0xfc52,
+    FusFwUpgrade = 0xfc54,
+    FusFwDelete = 0xfc55,
+    FusUpdateAuthKey = 0xfc56,
+    FusLockAuthKey = 0xfc57,
+    FusStoreUsrKey = 0xfc58,
+    FusLoadUsrKey = 0xfc59,
+    FusStartWs = 0xfc5a,
+    FusLockUsrKey = 0xfc5d,
+    FusUnloadUsrKey = 0xfc5e,
+    FusActivateAntirollback = 0xfc5f,
+
+    BleInit = 0xfc66,
+    ThreadInit = 0xfc67,
+    DebugInit = 0xfc68,
+    FlashEraseActivity = 0xfc69,
+    ConcurrentSetMode = 0xfc6a,
+    RadioAllowLowPower = 0xfc6d,
+    Mac802_15_4Init = 0xfc6e,
+    Reinit = 0xfc6f,
+    ZigbeeInit = 0xfc70,
+    ExtpaConfig = 0xfc72,
+    SetFlashActivityControl = 0xfc73,
+    Config = 0xfc75,
+    ConcurrentGetNextBleEvtTime = 0xfc76,
+}
+
+/// Status returned by CPU2 in the command complete event of an SHCI command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ShciStatus {
+    Success,
+    UnknownCmd,
+    MemoryCapacityExceeded,
+    UnsupportedFeature,
+    InvalidHciCmdParams,
+    InvalidParams,
+    /// the command is not supported by the FUS, usually because the FUS is not running
+    FusCmdNotSupported,
+    Other(u8),
+}
+
+impl From<u8> for ShciStatus {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => ShciStatus::Success,
+            0x01 => ShciStatus::UnknownCmd,
+            0x07 => ShciStatus::MemoryCapacityExceeded,
+            0x11 => ShciStatus::UnsupportedFeature,
+            0x12 => ShciStatus::InvalidHciCmdParams,
+            0x42 => ShciStatus::InvalidParams,
+            0xff => ShciStatus::FusCmdNotSupported,
+            other => ShciStatus::Other(other),
+        }
+    }
+}
+
+impl From<ShciStatus> for u8 {
+    fn from(value: ShciStatus) -> Self {
+        match value {
+            ShciStatus::Success => 0x00,
+            ShciStatus::UnknownCmd => 0x01,
+            ShciStatus::MemoryCapacityExceeded => 0x07,
+            ShciStatus::UnsupportedFeature => 0x11,
+            ShciStatus::InvalidHciCmdParams => 0x12,
+            ShciStatus::InvalidParams => 0x42,
+            ShciStatus::FusCmdNotSupported => 0xff,
+            ShciStatus::Other(other) => other,
+        }
+    }
+}
+
+/// Command complete event received in response to an SHCI command.
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct ShciResponse {
+    pub opcode: u16,
+    pub status: ShciStatus,
+    payload_len: u8,
+    payload: [u8; SHCI_RSP_PAYLOAD_LEN],
+}
+
+impl ShciResponse {
+    /// parses the payload of a command complete event, i.e. `num_cmd`, `opcode`, `status`
+    /// and the return parameters.
+    pub(super) fn parse(cc: &[u8]) -> Self {
+        let opcode = u16::from_le_bytes([cc[1], cc[2]]);
+        let status = ShciStatus::from(cc.get(3).copied().unwrap_or(0xff));
+
+        let rest = cc.get(4..).unwrap_or(&[]);
+        if rest.len() > SHCI_RSP_PAYLOAD_LEN {
+            defmt::warn!("SHCI response to {:#06x} truncated", opcode);
+        }
+
+        let payload_len = rest.len().min(SHCI_RSP_PAYLOAD_LEN);
+        let mut payload = [0u8; SHCI_RSP_PAYLOAD_LEN];
+        payload[..payload_len].copy_from_slice(&rest[..payload_len]);
+
+        Self {
+            opcode,
+            status,
+            payload_len: payload_len as u8,
+            payload,
+        }
+    }
+
+    /// return parameters following the status byte
+    pub fn payload(&self) -> &[u8] {
+        &self.payload[..self.payload_len as usize]
+    }
+
+    /// returns the length of the response once written with [`ShciResponse::write`]
+    pub fn size(&self) -> usize {
+        SHCI_RSP_HEADER_SIZE + self.payload_len as usize
+    }
+
+    /// writes the response as a command complete event of kind [`TlPacketType::SysRsp`] and
+    /// returns its length, like the events read from the HCI buffer
+    pub fn write(&self, buf: &mut [u8]) -> Result<usize, ()> {
+        let len = self.size();
+        if buf.len() < len {
+            return Err(());
+        }
+
+        buf[0] = TlPacketType::SysRsp as u8;
+        buf[1] = TL_BLEEVT_CC_OPCODE;
+        buf[2] = (len - TL_EVT_HEADER_SIZE) as u8;
+        buf[3] = 1;
+        buf[4..6].copy_from_slice(&self.opcode.to_le_bytes());
+        buf[6] = u8::from(self.status);
+        buf[SHCI_RSP_HEADER_SIZE..len].copy_from_slice(self.payload());
+
+        Ok(len)
+    }
+}
+
+/// `SHCI_C2_BLE_Init` parameters, in the layout of the latest supported BLE stack.
+///
+/// Older stacks only read a prefix of the structure, see
+/// [`BleStackVersion`](crate::shci::ble_init::BleStackVersion). Use
+/// [`BleInitBuilder`](crate::shci::ble_init::BleInitBuilder) to fill it in.
 #[derive(Debug, Clone, Copy)]
 #[repr(C, packed)]
 pub struct ShciBleInitCmdParam {
@@ -31,8 +178,25 @@ pub struct ShciBleInitCmdParam {
     pub max_conn_event_length: u32,
     pub hs_startup_time: u16,
     pub viterbi_enable: u8,
+    /// [`BleInitOptions`](crate::shci::ble_init::BleInitOptions), named after the only bit
+    /// known to stacks before v1.12
     pub ll_only: u8,
     pub hw_version: u8,
+
+    // v1.12
+    pub max_coc_initiator_nbr: u8,
+    pub min_tx_power: i8,
+    pub max_tx_power: i8,
+    pub rx_model_config: u8,
+
+    // v1.13
+    pub max_adv_set_nbr: u8,
+    pub max_adv_data_len: u16,
+    pub tx_path_compens: i16,
+    pub rx_path_compens: i16,
+
+    // v1.15
+    pub ble_core_version: u8,
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -52,35 +216,119 @@ pub const TL_BLE_EVT_CS_PACKET_SIZE: usize = TL_EVT_HEADER_SIZE + TL_CS_EVT_SIZE
 #[allow(dead_code)] // Not used currently but reserved
 const TL_BLE_EVT_CS_BUFFER_SIZE: usize = TL_PACKET_HEADER_SIZE + TL_BLE_EVT_CS_PACKET_SIZE;
 
-pub fn shci_ble_init(ipcc: &mut Ipcc, param: ShciBleInitCmdParam) {
-    defmt::debug!("sending shci init");
+/// Size of the `SHCI_C2_Config` parameters following `payload_cmd_size`.
+pub const SHCI_C2_CONFIG_PAYLOAD_CMD_SIZE: u8 = core::mem::size_of::<ShciConfigParam>() as u8 - 1;
+
+/// `config1` bits of [`ShciConfigParam`]
+pub mod config1 {
+    /// the BLE stack keeps its NVM data in SRAM at `ble_nvm_ram_address`
+    pub const BLE_NVM_DATA_TO_SRAM: u8 = 1 << 0;
+    /// the Thread stack keeps its NVM data in SRAM
+    pub const THREAD_NVM_DATA_TO_SRAM: u8 = 1 << 1;
+}
+
+/// `evt_mask1` bits of [`ShciConfigParam`], each enables a system event
+pub mod evt_mask1 {
+    pub const ERROR_NOTIF_ENABLE: u8 = 1 << 0;
+    pub const BLE_NVM_RAM_UPDATE_ENABLE: u8 = 1 << 1;
+    pub const THREAD_NVM_RAM_UPDATE_ENABLE: u8 = 1 << 2;
+    pub const NVM_START_WRITE_ENABLE: u8 = 1 << 3;
+    pub const NVM_END_WRITE_ENABLE: u8 = 1 << 4;
+    pub const NVM_START_ERASE_ENABLE: u8 = 1 << 5;
+    pub const NVM_END_ERASE_ENABLE: u8 = 1 << 6;
+}
+
+/// How CPU2 is told when CPU1 may not access the flash, see
+/// [`ShciOpcode::SetFlashActivityControl`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum FlashActivityControl {
+    /// CPU2 sets the PES bit of the flash controller
+    Pes = 0,
+    /// CPU2 holds the [`BlockFlashReqByCpu2`](crate::hsem::SemaphoreId::BlockFlashReqByCpu2)
+    /// semaphore
+    Sem7 = 1,
+}
+
+/// Radio stack whose low-power modes are allowed or forbidden with
+/// [`ShciOpcode::RadioAllowLowPower`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum RadioStack {
+    Ble = 0,
+    Thread = 1,
+    Zigbee = 2,
+}
+
+/// Radio stack that has priority in the concurrent firmwares, see
+/// [`ShciOpcode::ConcurrentSetMode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum ConcurrentMode {
+    Ble = 0,
+    Thread = 1,
+    Zigbee = 2,
+    Mac = 3,
+}
 
-    let mut packet = ShciBleInitCmdPacket {
-        header: ShciHeader::default(),
-        param,
-    };
+/// `SHCI_C2_Config` parameters
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C, packed)]
+pub struct ShciConfigParam {
+    /// size of the command payload, [`SHCI_C2_CONFIG_PAYLOAD_CMD_SIZE`] unless the wireless
+    /// stack is older than the `revision_id`/`device_id` fields
+    pub payload_cmd_size: u8,
+    pub config1: u8,
+    pub evt_mask1: u8,
+    pub spare1: u8,
+    pub ble_nvm_ram_address: u32,
+    pub thread_nvm_ram_address: u32,
+    pub revision_id: u16,
+    pub device_id: u16,
+}
 
-    let packet_ptr: *mut _ = &mut packet;
+/// `SHCI_C2_ExtpaConfig` parameters, see [`crate::fem`]
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C, packed)]
+pub struct ShciExtpaConfigParam {
+    /// base address of the GPIO port of the control pin
+    pub ctrl_port: u32,
+    /// mask of the control pin in its port
+    pub ctrl_pin: u16,
+    /// level of the control pin that enables the external PA, 0 for low and 1 for high
+    pub ctrl: u8,
+    /// 1 to enable the external PA control, 0 to disable it
+    pub state: u8,
+}
 
+/// views a packed SHCI parameter struct as the bytes sent to CPU2
+pub(crate) fn param_bytes<T: Copy>(param: &T) -> &[u8] {
     unsafe {
-        let cmd_ptr: *mut CmdPacket = packet_ptr.cast();
+        core::slice::from_raw_parts(param as *const T as *const u8, core::mem::size_of::<T>())
+    }
+}
 
-        (*cmd_ptr).cmdserial.cmd.cmd_code = SCHI_OPCODE_BLE_INIT;
-        (*cmd_ptr).cmdserial.cmd.payload_len = core::mem::size_of::<ShciBleInitCmdParam>() as u8;
+/// writes an SHCI command into the system command buffer and notifies CPU2.
+///
+/// The response is delivered by the IPCC TX interrupt, see
+/// [`TlMbox::pop_sys_rsp`](super::TlMbox::pop_sys_rsp). Fails if the payload does not fit in
+/// the command buffer.
+pub fn shci_send_cmd(ipcc: &mut Ipcc, opcode: u16, payload: &[u8]) -> Result<(), ()> {
+    defmt::debug!("sending SHCI {:#06x} {:#04x}", opcode, payload);
 
-        let mut p_cmd_buffer = &mut *(*TL_SYS_TABLE.as_mut_ptr()).pcmd_buffer;
-        core::ptr::write(p_cmd_buffer, *cmd_ptr);
+    unsafe {
+        let p_cmd_buffer = &mut *(*TL_SYS_TABLE.as_mut_ptr()).pcmd_buffer;
+        if payload.len() > p_cmd_buffer.cmdserial.cmd.payload.len() {
+            return Err(());
+        }
 
         p_cmd_buffer.cmdserial.ty = TlPacketType::SysCmd as u8;
+        p_cmd_buffer.cmdserial.cmd.cmd_code = opcode;
+        p_cmd_buffer.cmdserial.cmd.payload_len = payload.len() as u8;
+        p_cmd_buffer.cmdserial.cmd.payload[..payload.len()].copy_from_slice(payload);
+    }
 
-        let a = unsafe {
-            core::slice::from_raw_parts(
-                p_cmd_buffer as *const _ as *const u8,
-                core::mem::size_of::<CmdPacket>(),
-            )
-        };
-        defmt::debug!("sending SHCI {:#04x}", a);
+    sys::send_cmd(ipcc);
 
-        sys::send_cmd(ipcc);
-    }
+    Ok(())
 }
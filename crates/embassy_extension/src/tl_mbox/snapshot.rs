@@ -0,0 +1,179 @@
+//! Shared memory snapshots for debugging.
+//!
+//! When CPU2 stops responding, a [`MboxSnapshot`] tells what both cores last wrote into the
+//! mailbox: where the tables are, what is queued, which command is pending and which IPCC
+//! channels are masked or flagged.
+
+use core::{
+    mem::size_of,
+    ptr::{addr_of, read_volatile},
+};
+
+use super::{
+    cmd::CmdPacket, layout::in_shared_ram, TlMbox, BLE_CMD_BUFFER, CFG_TLBLE_EVT_QUEUE_LENGTH,
+    EVT_POOL, EVT_QUEUE, FREE_BUF_QUEUE, LOCAL_FREE_BUF_QUEUE, POOL_SIZE, SYSTEM_EVT_QUEUE,
+    SYS_CMD_BUF, TL_REF_TABLE,
+};
+use crate::{
+    ipcc::{Ipcc, IpccRegisters},
+    unsafe_linked_list::LinkedListNode,
+};
+
+/// Queues are walked at most this many nodes deep, so that a corrupted list cannot hang the dump.
+const MAX_QUEUE_WALK: usize = 64;
+
+/// Number of command payload bytes captured in a [`CmdSnapshot`].
+pub const CMD_PAYLOAD_SNAPSHOT_LEN: usize = 16;
+
+/// Addresses held by the reference table.
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct RefTableSnapshot {
+    pub address: u32,
+    pub device_info_table: u32,
+    pub ble_table: u32,
+    pub thread_table: u32,
+    pub sys_table: u32,
+    pub mem_manager_table: u32,
+    pub traces_table: u32,
+    pub mac_802_15_4_table: u32,
+}
+
+/// Head of a linked list queue.
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct QueueSnapshot {
+    pub head: u32,
+    pub next: u32,
+    pub prev: u32,
+    /// number of nodes in the queue, `None` if the queue is longer than expected or
+    /// is not properly linked
+    pub len: Option<usize>,
+    /// a node points outside of the shared SRAM, the queue was walked up to that node only
+    pub corrupt: bool,
+}
+
+/// Occupancy of the event buffer pool shared with CPU2.
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct PoolSnapshot {
+    pub address: u32,
+    pub size: usize,
+    /// number of event buffers the pool was sized for
+    pub buffers: usize,
+    /// events received from CPU2 and not yet processed by CPU1
+    pub pending_events: usize,
+}
+
+/// Command currently sitting in a command buffer.
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct CmdSnapshot {
+    pub address: u32,
+    pub ty: u8,
+    pub opcode: u16,
+    pub payload_len: u8,
+    /// first [`CMD_PAYLOAD_SNAPSHOT_LEN`] bytes of the payload
+    pub payload: [u8; CMD_PAYLOAD_SNAPSHOT_LEN],
+}
+
+/// Snapshot of the shared memory and of the IPCC state.
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct MboxSnapshot {
+    pub ref_table: RefTableSnapshot,
+
+    pub evt_queue: QueueSnapshot,
+    pub system_evt_queue: QueueSnapshot,
+    pub free_buf_queue: QueueSnapshot,
+    pub local_free_buf_queue: QueueSnapshot,
+
+    pub evt_pool: PoolSnapshot,
+
+    pub ble_cmd: CmdSnapshot,
+    pub sys_cmd: CmdSnapshot,
+
+    pub ipcc: IpccRegisters,
+}
+
+impl TlMbox {
+    /// takes a snapshot of the mailbox shared memory and of the IPCC registers.
+    ///
+    /// Only reads memory, so it is safe to call it from a fault handler or while CPU2 is stuck.
+    pub fn snapshot(&self, ipcc: &Ipcc) -> MboxSnapshot {
+        unsafe {
+            let ref_table = read_volatile(TL_REF_TABLE.as_ptr());
+
+            MboxSnapshot {
+                ref_table: RefTableSnapshot {
+                    address: TL_REF_TABLE.as_ptr() as u32,
+                    device_info_table: ref_table.device_info_table as u32,
+                    ble_table: ref_table.ble_table as u32,
+                    thread_table: ref_table.thread_table as u32,
+                    sys_table: ref_table.sys_table as u32,
+                    mem_manager_table: ref_table.mem_manager_table as u32,
+                    traces_table: ref_table.traces_table as u32,
+                    mac_802_15_4_table: ref_table.mac_802_15_4_table as u32,
+                },
+
+                evt_queue: queue_snapshot(EVT_QUEUE.as_mut_ptr()),
+                system_evt_queue: queue_snapshot(SYSTEM_EVT_QUEUE.as_mut_ptr()),
+                free_buf_queue: queue_snapshot(FREE_BUF_QUEUE.as_mut_ptr()),
+                local_free_buf_queue: queue_snapshot(LOCAL_FREE_BUF_QUEUE.as_mut_ptr()),
+
+                evt_pool: PoolSnapshot {
+                    address: EVT_POOL.as_ptr() as u32,
+                    size: POOL_SIZE,
+                    buffers: CFG_TLBLE_EVT_QUEUE_LENGTH,
+                    pending_events: self.evt_queue.len() + self.sys_evt_queue.len(),
+                },
+
+                ble_cmd: cmd_snapshot(BLE_CMD_BUFFER.as_ptr()),
+                sys_cmd: cmd_snapshot(SYS_CMD_BUF.as_ptr()),
+
+                ipcc: ipcc.registers(),
+            }
+        }
+    }
+}
+
+unsafe fn queue_snapshot(head: *mut LinkedListNode) -> QueueSnapshot {
+    let next = read_volatile(addr_of!((*head).next));
+    let prev = read_volatile(addr_of!((*head).prev));
+
+    let mut len = None;
+    let mut corrupt = false;
+    let mut node = next;
+    for i in 0..=MAX_QUEUE_WALK {
+        if node == head {
+            len = Some(i);
+            break;
+        }
+
+        // reading a node outside of the shared SRAM could fault
+        if !in_shared_ram(node as usize, size_of::<LinkedListNode>()) {
+            corrupt = true;
+            break;
+        }
+
+        node = read_volatile(addr_of!((*node).next));
+    }
+
+    QueueSnapshot {
+        head: head as u32,
+        next: next as u32,
+        prev: prev as u32,
+        len,
+        corrupt,
+    }
+}
+
+unsafe fn cmd_snapshot(buffer: *const CmdPacket) -> CmdSnapshot {
+    let packet = read_volatile(buffer);
+
+    let mut payload = [0u8; CMD_PAYLOAD_SNAPSHOT_LEN];
+    payload.copy_from_slice(&packet.cmdserial.cmd.payload[..CMD_PAYLOAD_SNAPSHOT_LEN]);
+
+    CmdSnapshot {
+        address: buffer as u32,
+        ty: packet.cmdserial.ty,
+        opcode: packet.cmdserial.cmd.cmd_code,
+        payload_len: packet.cmdserial.cmd.payload_len,
+        payload,
+    }
+}
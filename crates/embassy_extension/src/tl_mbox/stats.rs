@@ -0,0 +1,127 @@
+//! Mailbox health counters.
+//!
+//! The counters are updated from both interrupt and thread context, so they are plain atomics
+//! that can be read and reset at any time without taking the mailbox.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// A single event counter or high-water mark.
+pub(crate) struct Counter(AtomicU32);
+
+impl Counter {
+    pub(crate) const fn new() -> Self {
+        Self(AtomicU32::new(0))
+    }
+
+    pub(crate) fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add(&self, value: u32) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set(&self, value: u32) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    /// records `value` if it is greater than the current high-water mark
+    pub(crate) fn record_max(&self, value: u32) {
+        self.0.fetch_max(value, Ordering::Relaxed);
+    }
+
+    pub(crate) fn get(&self) -> u32 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn reset(&self) {
+        self.0.store(0, Ordering::Relaxed);
+    }
+}
+
+pub(super) struct MboxCounters {
+    pub sys_events: Counter,
+    pub ble_events: Counter,
+    pub evt_queue_high_water: Counter,
+    pub evt_queue_overflows: Counter,
+    pub sys_commands: Counter,
+    pub sys_responses: Counter,
+    pub ble_commands: Counter,
+    pub acl_packets_sent: Counter,
+    pub acl_packets_acked: Counter,
+    pub buffers_released: Counter,
+    pub release_deferrals: Counter,
+}
+
+pub(super) static COUNTERS: MboxCounters = MboxCounters {
+    sys_events: Counter::new(),
+    ble_events: Counter::new(),
+    evt_queue_high_water: Counter::new(),
+    evt_queue_overflows: Counter::new(),
+    sys_commands: Counter::new(),
+    sys_responses: Counter::new(),
+    ble_commands: Counter::new(),
+    acl_packets_sent: Counter::new(),
+    acl_packets_acked: Counter::new(),
+    buffers_released: Counter::new(),
+    release_deferrals: Counter::new(),
+};
+
+/// Snapshot of the mailbox counters, see [`TlMbox::stats`](super::TlMbox::stats).
+#[derive(Debug, Clone, Copy, Default, defmt::Format)]
+pub struct MboxStats {
+    /// events received on the system event channel
+    pub sys_events: u32,
+    /// events received on the BLE event channel
+    pub ble_events: u32,
+    /// largest number of events waiting in the mailbox event queue
+    pub evt_queue_high_water: u32,
+    /// events dropped because the mailbox event queue was full
+    pub evt_queue_overflows: u32,
+    /// commands sent on the system channel
+    pub sys_commands: u32,
+    /// command responses received on the system channel
+    pub sys_responses: u32,
+    /// commands sent on the BLE command channel
+    pub ble_commands: u32,
+    /// ACL packets sent on the ACL data channel
+    pub acl_packets_sent: u32,
+    /// ACL packets whose buffer CPU2 released
+    pub acl_packets_acked: u32,
+    /// event buffers released back to CPU2
+    pub buffers_released: u32,
+    /// buffer releases postponed because the release channel was still busy
+    pub release_deferrals: u32,
+}
+
+impl MboxCounters {
+    pub(super) fn snapshot(&self) -> MboxStats {
+        MboxStats {
+            sys_events: self.sys_events.get(),
+            ble_events: self.ble_events.get(),
+            evt_queue_high_water: self.evt_queue_high_water.get(),
+            evt_queue_overflows: self.evt_queue_overflows.get(),
+            sys_commands: self.sys_commands.get(),
+            sys_responses: self.sys_responses.get(),
+            ble_commands: self.ble_commands.get(),
+            acl_packets_sent: self.acl_packets_sent.get(),
+            acl_packets_acked: self.acl_packets_acked.get(),
+            buffers_released: self.buffers_released.get(),
+            release_deferrals: self.release_deferrals.get(),
+        }
+    }
+
+    pub(super) fn reset(&self) {
+        self.sys_events.reset();
+        self.ble_events.reset();
+        self.evt_queue_high_water.reset();
+        self.evt_queue_overflows.reset();
+        self.sys_commands.reset();
+        self.sys_responses.reset();
+        self.ble_commands.reset();
+        self.acl_packets_sent.reset();
+        self.acl_packets_acked.reset();
+        self.buffers_released.reset();
+        self.release_deferrals.reset();
+    }
+}
@@ -1,7 +1,10 @@
 use super::{
     channels,
     cmd::{CmdPacket, CmdSerial},
-    evt::{CcEvt, EvtBox, EvtSerial},
+    enqueue_evt,
+    evt::{EvtBox, EvtSerial},
+    shci::ShciResponse,
+    stats::COUNTERS,
     HeaplessEvtQueue, SysTable, SYSTEM_EVT_QUEUE, SYS_CMD_BUF, TL_SYS_TABLE,
 };
 use crate::{
@@ -27,8 +30,9 @@ impl Sys {
         Sys
     }
 
-    pub fn cmd_evt_handler(&self, ipcc: &mut Ipcc) -> CcEvt {
+    pub fn cmd_evt_handler(&self, ipcc: &mut Ipcc) -> ShciResponse {
         ipcc.c1_set_tx_channel(channels::cpu1::IPCC_SYSTEM_CMD_RSP_CHANNEL, false);
+        COUNTERS.sys_responses.increment();
 
         // ST's command response data structure is really convoluted.
         //
@@ -42,18 +46,16 @@ impl Sys {
         unsafe {
             let pcmd: *const CmdPacket = (*TL_SYS_TABLE.as_ptr()).pcmd_buffer;
 
-            let a = unsafe {
-                core::slice::from_raw_parts(
-                    &pcmd as *const _ as *const u8,
-                    core::mem::size_of::<CmdPacket>(),
-                )
-            };
-            defmt::debug!("sys evt handler {:#04x}", a);
-
             let cmd_serial: *const CmdSerial = &(*pcmd).cmdserial;
             let evt_serial: *const EvtSerial = cmd_serial.cast();
-            let cc: *const CcEvt = (*evt_serial).evt.payload.as_ptr().cast();
-            *cc
+            let cc: *const u8 = (*evt_serial).evt.payload.as_ptr();
+
+            // the payload of a CcEvt holds at least `num_cmd`, `cmd_code` and the status
+            let len = ((*evt_serial).evt.payload_len as usize).max(4);
+            let cc = core::slice::from_raw_parts(cc, len);
+            defmt::debug!("sys evt handler {:#04x}", cc);
+
+            ShciResponse::parse(cc)
         }
     }
 
@@ -68,7 +70,8 @@ impl Sys {
                 let event = node_ptr.cast();
                 let event = EvtBox::new(event);
 
-                queue.enqueue(event).unwrap();
+                COUNTERS.sys_events.increment();
+                enqueue_evt(queue, event);
             }
         }
 
@@ -77,6 +80,8 @@ impl Sys {
 }
 
 pub fn send_cmd(ipcc: &mut Ipcc) {
+    COUNTERS.sys_commands.increment();
+
     ipcc.c1_set_flag_channel(channels::cpu1::IPCC_SYSTEM_CMD_RSP_CHANNEL);
     ipcc.c1_set_tx_channel(channels::cpu1::IPCC_SYSTEM_CMD_RSP_CHANNEL, true);
 }
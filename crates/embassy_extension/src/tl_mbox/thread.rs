@@ -0,0 +1,47 @@
+use super::{
+    channels, ThreadTable, THREAD_CLI_CMD_BUFFER, THREAD_NOTIF_RSP_EVT_BUFFER,
+    THREAD_OT_CMD_BUFFER, TL_THREAD_TABLE,
+};
+use crate::ipcc::Ipcc;
+
+/// Thread transport layer, only the buffers are registered.
+///
+/// CPU2 accepts the Thread init command once the buffers are registered, e.g. to share the radio
+/// with the concurrent BLE + Thread firmware, see [`crate::concurrent`]. There is no OpenThread
+/// command path: notifications of CPU2 are acknowledged and dropped.
+pub struct Thread;
+
+impl Thread {
+    pub(super) fn new(ipcc: &mut Ipcc) -> Self {
+        unsafe {
+            THREAD_OT_CMD_BUFFER = core::mem::MaybeUninit::zeroed();
+            THREAD_NOTIF_RSP_EVT_BUFFER = core::mem::MaybeUninit::zeroed();
+            THREAD_CLI_CMD_BUFFER = core::mem::MaybeUninit::zeroed();
+
+            TL_THREAD_TABLE.as_mut_ptr().write_volatile(ThreadTable {
+                nostack_buffer: THREAD_NOTIF_RSP_EVT_BUFFER.as_ptr().cast(),
+                clicmdrsp_buffer: THREAD_CLI_CMD_BUFFER.as_ptr().cast(),
+                otcmdrsp_buffer: THREAD_OT_CMD_BUFFER.as_ptr().cast(),
+            });
+        }
+
+        ipcc.c1_set_rx_channel(channels::cpu2::IPCC_THREAD_NOTIFICATION_ACK_CHANNEL, true);
+        ipcc.c1_set_rx_channel(channels::cpu2::IPCC_THREAD_CLI_NOTIFICATION_ACK_CHANNEL, true);
+
+        Thread
+    }
+
+    /// acknowledges a notification of the OpenThread stack
+    pub(super) fn notification_handler(&self, ipcc: &mut Ipcc) {
+        defmt::debug!("thread notification");
+
+        ipcc.c1_clear_flag_channel(channels::cpu2::IPCC_THREAD_NOTIFICATION_ACK_CHANNEL);
+    }
+
+    /// acknowledges a notification of the Thread CLI
+    pub(super) fn cli_notification_handler(&self, ipcc: &mut Ipcc) {
+        defmt::debug!("thread cli notification");
+
+        ipcc.c1_clear_flag_channel(channels::cpu2::IPCC_THREAD_CLI_NOTIFICATION_ACK_CHANNEL);
+    }
+}